@@ -0,0 +1,496 @@
+use anyhow::{Context, Result, anyhow};
+use clap::Parser;
+use niri_ipc::Action;
+use serde::Deserialize;
+use std::{
+    cell::OnceCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{Direction, GlobalArgs};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Margins used when snapping floating windows to the edges of the output
+    margins: MarginsConfig,
+    /// Align the window itself with the margins when snapping, instead of its tile
+    pub account_border: bool,
+    /// Rules applied by `watch` to newly floating windows
+    #[serde(rename = "rule")]
+    pub rules: Vec<Rule>,
+    /// Layouts applied by `layout`
+    #[serde(rename = "layout")]
+    layouts: Vec<Layout>,
+    /// Actions `floating-snap-or` runs for tiled windows when none is given, by direction, or
+    /// `none` for no action
+    #[serde(rename = "or_action")]
+    or_action_lines: OrActionLines,
+    #[serde(skip)]
+    or_actions: HashMap<Direction, Action>,
+    /// Positions `floating-cycle` moves through
+    cycle: Option<Vec<Direction>>,
+    /// Widths `column-width` chooses from, in percent of the output width
+    column_widths: Option<Vec<f64>>,
+    /// Name of the workspace `floating-stash` moves windows to
+    stash_workspace: Option<String>,
+    /// Number of window moves `undo` can revert, 0 disables the history
+    history: Option<usize>,
+    /// Commands run by `hooks`
+    pub hooks: Hooks,
+    /// Path or name in `$PATH` of the nirius binary used by `toggle-follow-mode`
+    pub nirius_path: Option<PathBuf>,
+    /// Margins from the environment and the command line, which replace those in `margins`
+    #[serde(skip)]
+    margin_overrides: PartialMargins,
+    /// Use a bottom margin of 0 unless waybar is running
+    detect_waybar: bool,
+    #[serde(skip)]
+    waybar_running: OnceCell<bool>,
+}
+
+/// The `[hooks]` table.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Shell command run when a window gets focused
+    pub on_focus: Option<String>,
+    /// Milliseconds the focus has to stay on a window before the command runs
+    debounce_ms: Option<u64>,
+}
+
+/// The `[or_action]` table, with the same names and aliases as the directions on the command line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct OrActionLines {
+    #[serde(alias = "l", alias = "west")]
+    left: Option<String>,
+    #[serde(alias = "d", alias = "south")]
+    down: Option<String>,
+    #[serde(alias = "u", alias = "north")]
+    up: Option<String>,
+    #[serde(alias = "r", alias = "east")]
+    right: Option<String>,
+    center: Option<String>,
+    top_left: Option<String>,
+    top_right: Option<String>,
+    bottom_left: Option<String>,
+    bottom_right: Option<String>,
+}
+
+impl OrActionLines {
+    /// The directions that have an action line, with it.
+    fn lines(&self) -> impl Iterator<Item = (Direction, &str)> {
+        [
+            (Direction::Left, &self.left),
+            (Direction::Down, &self.down),
+            (Direction::Up, &self.up),
+            (Direction::Right, &self.right),
+            (Direction::Center, &self.center),
+            (Direction::TopLeft, &self.top_left),
+            (Direction::TopRight, &self.top_right),
+            (Direction::BottomLeft, &self.bottom_left),
+            (Direction::BottomRight, &self.bottom_right),
+        ]
+        .into_iter()
+        .filter_map(|(direction, line)| Some((direction, line.as_deref()?)))
+    }
+}
+
+/// A `[[rule]]` entry.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// App id of the windows this rule applies to
+    pub app_id: String,
+    /// Where to snap the windows
+    pub snap: Direction,
+}
+
+/// A `[[layout]]` entry.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    /// Name given to `layout`
+    pub name: String,
+    /// Where to put the floating windows of each app
+    #[serde(rename = "window", default)]
+    pub windows: Vec<LayoutWindow>,
+}
+
+/// A `[[layout.window]]` entry.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutWindow {
+    /// App id of the windows this entry applies to
+    pub app_id: String,
+    /// Where to snap the windows, they are not moved if omitted
+    pub snap: Option<Direction>,
+    /// Width in percent of the output width, unchanged if omitted
+    pub width_pct: Option<f64>,
+    /// Height in percent of the output height, unchanged if omitted
+    pub height_pct: Option<f64>,
+}
+
+/// The `[margins]` table.
+///
+/// The margins can be given directly in the table, or per output in `[margins.<output-name>]`
+/// tables with `[margins.default]` used for the other outputs. Sides missing from the table of an
+/// output are taken from `[margins.default]`, then from `[margins]` itself and then from the
+/// built-in defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(try_from = "HashMap<String, toml::Value>")]
+struct MarginsConfig {
+    global: PartialMargins,
+    outputs: HashMap<String, PartialMargins>,
+}
+
+impl TryFrom<HashMap<String, toml::Value>> for MarginsConfig {
+    type Error = String;
+
+    fn try_from(table: HashMap<String, toml::Value>) -> Result<Self, Self::Error> {
+        let mut margins = Self::default();
+        for (key, value) in table {
+            if let Some(side) = MarginSide::ALL.into_iter().find(|s| s.name() == key) {
+                let margin = value
+                    .try_into()
+                    .map_err(|e| format!("margins.{key}: {e}"))?;
+                *margins.global.side(side) = Some(margin);
+            } else {
+                // Anything else is the table of an output, so that a misspelled side fails too.
+                let output = value
+                    .try_into()
+                    .map_err(|e| format!("margins.{key}: {e}"))?;
+                margins.outputs.insert(key, output);
+            }
+        }
+        Ok(margins)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Margins {
+    pub left: Margin,
+    pub right: Margin,
+    pub top: Margin,
+    pub bottom: Margin,
+}
+
+impl Default for Margins {
+    fn default() -> Self {
+        Self {
+            left: Margin::Pixels(0.),
+            right: Margin::Pixels(0.),
+            top: Margin::Pixels(0.),
+            bottom: Margin::Pixels(48.),
+        }
+    }
+}
+
+/// A margin given as a number of pixels, or as a string like `"5%"` in percent of the width of
+/// the output for left and right, and of its height for top and bottom.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "MarginValue")]
+pub enum Margin {
+    Pixels(f64),
+    Percent(f64),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MarginValue {
+    Number(f64),
+    String(String),
+}
+
+impl TryFrom<MarginValue> for Margin {
+    type Error = String;
+
+    fn try_from(value: MarginValue) -> Result<Self, Self::Error> {
+        match value {
+            MarginValue::Number(pixels) => Ok(Self::Pixels(pixels)),
+            MarginValue::String(s) => s
+                .strip_suffix('%')
+                .and_then(|pct| pct.trim().parse().ok())
+                .map(Self::Percent)
+                .ok_or_else(|| format!("invalid margin {s:?}, expected pixels or a percentage")),
+        }
+    }
+}
+
+impl FromStr for Margin {
+    type Err = String;
+
+    /// Parse a margin like `48` or `5%`, as given in the environment or on the command line.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().parse() {
+            Ok(pixels) => Ok(Self::Pixels(pixels)),
+            Err(_) => MarginValue::String(s.to_string()).try_into(),
+        }
+    }
+}
+
+/// Margins for some of the sides, as given in a table of `[margins]` or by the environment and the
+/// command line.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PartialMargins {
+    left: Option<Margin>,
+    right: Option<Margin>,
+    top: Option<Margin>,
+    bottom: Option<Margin>,
+}
+
+impl PartialMargins {
+    fn side(&mut self, side: MarginSide) -> &mut Option<Margin> {
+        match side {
+            MarginSide::Left => &mut self.left,
+            MarginSide::Right => &mut self.right,
+            MarginSide::Top => &mut self.top,
+            MarginSide::Bottom => &mut self.bottom,
+        }
+    }
+
+    /// Take the margins from `$MYNIRI_MARGIN_LEFT` and the like, warning about invalid ones.
+    fn from_env() -> Self {
+        let mut overrides = Self::default();
+        for side in MarginSide::ALL {
+            let name = format!("MYNIRI_MARGIN_{}", side.name().to_uppercase());
+            let Some(value) = std::env::var_os(&name) else {
+                continue;
+            };
+            match value.to_string_lossy().parse() {
+                Ok(margin) => *overrides.side(side) = Some(margin),
+                Err(e) => eprintln!("warning: ignoring ${name}: {e}"),
+            }
+        }
+        overrides
+    }
+
+    /// These margins, with the missing sides taken from `fallback`.
+    fn or(self, fallback: Self) -> Self {
+        Self {
+            left: self.left.or(fallback.left),
+            right: self.right.or(fallback.right),
+            top: self.top.or(fallback.top),
+            bottom: self.bottom.or(fallback.bottom),
+        }
+    }
+
+    /// These margins, with the missing sides taken from `defaults`.
+    fn unwrap_or(self, defaults: Margins) -> Margins {
+        Margins {
+            left: self.left.unwrap_or(defaults.left),
+            right: self.right.unwrap_or(defaults.right),
+            top: self.top.unwrap_or(defaults.top),
+            bottom: self.bottom.unwrap_or(defaults.bottom),
+        }
+    }
+}
+
+/// A side of the output that has a margin, as given to `--margin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarginSide {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl MarginSide {
+    const ALL: [Self; 4] = [Self::Left, Self::Right, Self::Top, Self::Bottom];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Top => "top",
+            Self::Bottom => "bottom",
+        }
+    }
+}
+
+/// Parse a `--margin` like `bottom=48` or `left=5%`.
+pub fn parse_margin_arg(arg: &str) -> Result<(MarginSide, Margin), String> {
+    let (side, margin) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected SIDE=MARGIN, like bottom=48, not {arg:?}"))?;
+    let side = MarginSide::ALL
+        .into_iter()
+        .find(|s| s.name() == side)
+        .ok_or_else(|| format!("invalid side {side:?}, expected left, right, top or bottom"))?;
+    Ok((side, margin.parse()?))
+}
+
+impl Margin {
+    /// The margin in pixels, for an output that is `size` pixels wide or high.
+    pub fn resolve(self, size: f64) -> f64 {
+        match self {
+            Self::Pixels(pixels) => pixels,
+            Self::Percent(pct) => size * pct / 100.,
+        }
+    }
+}
+
+impl Config {
+    /// The margins of the output with the given connector name.
+    pub fn margins(&self, output: &str) -> Margins {
+        let table = |name| self.margins.outputs.get(name).copied().unwrap_or_default();
+        let mut configured = table(output)
+            .or(table("default"))
+            .or(self.margins.global)
+            .unwrap_or(Margins::default());
+        if self.detect_waybar && !self.waybar_running() {
+            configured.bottom = Margin::Pixels(0.);
+        }
+
+        self.margin_overrides.unwrap_or(configured)
+    }
+
+    /// Whether a waybar process is running, checked once for this config.
+    fn waybar_running(&self) -> bool {
+        *self.waybar_running.get_or_init(|| {
+            let Ok(entries) = std::fs::read_dir("/proc") else {
+                return false;
+            };
+            entries.flatten().any(|entry| {
+                std::fs::read_to_string(entry.path().join("comm"))
+                    .is_ok_and(|comm| comm.trim_end() == "waybar")
+            })
+        })
+    }
+
+    /// The `[[layout]]` with the given name.
+    pub fn layout(&self, name: &str) -> Option<&Layout> {
+        self.layouts.iter().find(|layout| layout.name == name)
+    }
+
+    /// The action `floating-snap-or` runs for tiled windows when none is given.
+    pub fn or_action(&self, direction: &Direction) -> Option<&Action> {
+        self.or_actions.get(direction)
+    }
+
+    /// The positions `floating-cycle` moves through, in order.
+    pub fn cycle(&self) -> &[Direction] {
+        self.cycle.as_deref().unwrap_or(&[
+            Direction::BottomRight,
+            Direction::TopRight,
+            Direction::TopLeft,
+            Direction::BottomLeft,
+            Direction::Center,
+        ])
+    }
+
+    /// The widths `column-width` chooses from, in percent of the output width.
+    pub fn column_widths(&self) -> &[f64] {
+        self.column_widths
+            .as_deref()
+            .unwrap_or(&[25., 100. / 3., 50., 200. / 3.])
+    }
+
+    /// The name of the workspace `floating-stash` moves windows to.
+    pub fn stash_workspace(&self) -> &str {
+        self.stash_workspace.as_deref().unwrap_or("stash")
+    }
+
+    /// The number of entries kept in the history of window moves.
+    pub fn history(&self) -> usize {
+        self.history.unwrap_or(100)
+    }
+
+    /// Load the config given with `--config` and apply the margins from the environment and from
+    /// `--margin`, in this order.
+    pub fn for_args(args: &GlobalArgs) -> Result<Self> {
+        let mut config = Self::load(args.config.as_deref())?;
+
+        config.margin_overrides = PartialMargins::from_env();
+        for &(side, margin) in &args.margins {
+            *config.margin_overrides.side(side) = Some(margin);
+        }
+
+        Ok(config)
+    }
+
+    /// Load the config from `path`, or from the default location if `path` is `None`.
+    ///
+    /// A missing file at the default location is not an error and yields the default config.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, explicit) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if !explicit && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+
+        let mut config: Self = toml::from_str(&content)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+
+        for (direction, line) in config.or_action_lines.lines() {
+            // `none` states in the config that tiled windows are left alone.
+            if line.trim() == "none" {
+                continue;
+            }
+            let action = parse_action(line).with_context(|| {
+                format!("invalid or_action for {direction:?} in {}", path.display())
+            })?;
+            config.or_actions.insert(direction, action);
+        }
+
+        Ok(config)
+    }
+}
+
+impl Hooks {
+    /// How long the focus has to stay on a window before `on_focus` runs.
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms.unwrap_or(100))
+    }
+}
+
+/// A niri action as a command line, like `focus-column-left` or `set-column-width 50%`.
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct ActionLine {
+    #[command(subcommand)]
+    action: Action,
+}
+
+fn parse_action(line: &str) -> Result<Action> {
+    let words = shlex::split(line).ok_or_else(|| anyhow!("unbalanced quotes"))?;
+    Ok(ActionLine::try_parse_from(words)?.action)
+}
+
+/// `$XDG_CONFIG_HOME/myniri/config.toml`, falling back to `~/.config/myniri/config.toml`.
+fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join("myniri").join("config.toml"))
+}
+
+/// `$XDG_STATE_HOME/myniri`, falling back to `~/.local/state/myniri`.
+pub fn state_dir() -> Result<PathBuf> {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .ok_or_else(|| anyhow!("neither $XDG_STATE_HOME nor $HOME is set"))?;
+
+    Ok(state_home.join("myniri"))
+}
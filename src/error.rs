@@ -0,0 +1,38 @@
+use std::fmt;
+
+/// Failures that scripts can tell apart by the exit code of myniri.
+///
+/// Other errors exit with 1.
+#[derive(Debug)]
+pub enum Failure {
+    /// A command acts on the focused window while no window is focused.
+    ///
+    /// This is common for keybinds pressed on an empty workspace, so it is not printed.
+    NoFocusedWindow,
+    /// A command for floating windows was run on a tiled window.
+    NotFloating,
+    /// niri couldn't be reached.
+    Connection,
+}
+
+impl Failure {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Failure::NoFocusedWindow => 2,
+            Failure::NotFloating => 3,
+            Failure::Connection => 4,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Failure::NoFocusedWindow => "no window is focused",
+            Failure::NotFloating => "the window is not floating",
+            Failure::Connection => "niri is not reachable",
+        })
+    }
+}
+
+impl std::error::Error for Failure {}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use niri_ipc::{Action, Event, WorkspaceReferenceArg};
+use std::{collections::BTreeSet, ops::ControlFlow};
+
+use crate::{GlobalArgs, ipc::Niri, state};
+
+/// State file with the ids of the windows in follow mode.
+const FOLLOWED: &str = "follow.json";
+
+/// Put the window with `id` in follow mode or take it out.
+pub fn toggle(id: u64, dry_run: bool) -> Result<()> {
+    let followed: BTreeSet<u64> = state::load(FOLLOWED)?;
+    set(id, !followed.contains(&id), dry_run)?;
+    Ok(())
+}
+
+/// Put the window with `id` in follow mode if `following`, or else take it out, and return
+/// whether that changed anything.
+pub fn set(id: u64, following: bool, dry_run: bool) -> Result<bool> {
+    let mut followed: BTreeSet<u64> = state::load(FOLLOWED)?;
+
+    let changed = if following {
+        followed.insert(id)
+    } else {
+        followed.remove(&id)
+    };
+    if !changed {
+        return Ok(false);
+    }
+
+    if dry_run {
+        eprintln!("follow mode for window {id}: {following}");
+    } else {
+        log::info!("follow mode for window {id}: {following}");
+        state::save(FOLLOWED, &followed)?;
+    }
+
+    Ok(true)
+}
+
+/// Move the windows in follow mode to every workspace that gets focused, reconnecting whenever the
+/// connection drops.
+pub fn run(args: &GlobalArgs) -> Result<()> {
+    Niri::for_each_event_reconnecting(args, |niri, event| {
+        match event {
+            Event::WorkspaceActivated { id, focused: true } => {
+                // The state file is read again every time, so that toggling doesn't need to reach
+                // this process.
+                let followed: BTreeSet<u64> = state::load(FOLLOWED)?;
+                if followed.is_empty() {
+                    return Ok(ControlFlow::Continue(()));
+                }
+
+                for window in niri.windows()? {
+                    if followed.contains(&window.id)
+                        && window.is_floating
+                        && window.workspace_id != Some(id)
+                    {
+                        niri.action(Action::MoveWindowToWorkspace {
+                            window_id: Some(window.id),
+                            reference: WorkspaceReferenceArg::Id(id),
+                            focus: false,
+                        })?;
+                    }
+                }
+            }
+            Event::WindowClosed { id } => {
+                let mut followed: BTreeSet<u64> = state::load(FOLLOWED)?;
+                if followed.remove(&id) && !niri.dry_run() {
+                    state::save(FOLLOWED, &followed)?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(ControlFlow::Continue(()))
+    })
+}
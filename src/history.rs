@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+};
+
+use crate::state;
+
+/// Position of a floating window before myniri moved it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Append `new` to the history, dropping the oldest entries beyond `limit`.
+pub fn record(new: Vec<Entry>, limit: usize) -> Result<()> {
+    let mut entries = load()?;
+    entries.extend(new);
+
+    let excess = entries.len().saturating_sub(limit);
+    entries.drain(..excess);
+
+    save(&entries)
+}
+
+/// The entries of the history, oldest first.
+pub fn load() -> Result<Vec<Entry>> {
+    let path = path()?;
+
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("failed to read {}", path.display()))?;
+        // A line cut short by a crash shouldn't make the rest of the history unusable.
+        if let Ok(entry) = serde_json::from_str(&line) {
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Replace the history with `entries`.
+pub fn save(entries: &[Entry]) -> Result<()> {
+    let path = path()?;
+
+    let mut content = String::new();
+    for entry in entries {
+        content.push_str(&serde_json::to_string(entry)?);
+        content.push('\n');
+    }
+
+    fs::write(&path, content).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn path() -> Result<PathBuf> {
+    state::path("history.jsonl")
+}
@@ -0,0 +1,108 @@
+use anyhow::{Result, bail};
+use niri_ipc::{
+    Event,
+    state::{EventStreamState, EventStreamStatePart},
+};
+use std::{
+    ops::ControlFlow,
+    process::{Child, Command, Stdio},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{GlobalArgs, config::Config, ipc::Niri};
+
+/// How often to check whether the previous hook finished while another one is due.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Run the `on_focus` hook of the config whenever the focus settles on a window, reconnecting
+/// whenever the connection drops.
+pub fn run(args: &GlobalArgs) -> Result<()> {
+    let config = Config::for_args(args)?;
+    let Some(command) = &config.hooks.on_focus else {
+        bail!("there is no on_focus in the [hooks] of the config")
+    };
+    let debounce = config.hooks.debounce();
+
+    // The events are read on another thread, so that waiting for them can time out when a hook is
+    // due.
+    let (sender, events) = mpsc::channel();
+    let event_args = args.clone();
+    thread::spawn(move || {
+        Niri::for_each_event_reconnecting(&event_args, |_, event| {
+            Ok(match sender.send(event) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
+            })
+        })
+    });
+
+    let mut state = EventStreamState::default();
+    // The window focused last and when the hook is due for it.
+    let mut pending: Option<(u64, Instant)> = None;
+    let mut last = None;
+    let mut running: Option<Child> = None;
+
+    loop {
+        let timeout = match pending {
+            Some((_, due)) => due.saturating_duration_since(Instant::now()),
+            None => Duration::MAX,
+        };
+        match events.recv_timeout(timeout) {
+            Ok(event) => {
+                if let Event::WindowFocusChanged { id } = &event {
+                    pending = id.map(|id| (id, Instant::now() + debounce));
+                }
+                state.apply(event);
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => bail!("stopped reading events"),
+        }
+
+        let Some((id, _)) = pending else {
+            continue;
+        };
+
+        // The hook for the previous window has to finish first, meanwhile the focus can still
+        // change again.
+        if let Some(child) = &mut running
+            && child.try_wait()?.is_none()
+        {
+            pending = Some((id, Instant::now() + POLL_INTERVAL));
+            continue;
+        }
+        pending = None;
+        running = None;
+
+        if last == Some(id) {
+            continue;
+        }
+        let Some(window) = state.windows.windows.get(&id) else {
+            continue;
+        };
+        last = Some(id);
+
+        if args.dry_run {
+            eprintln!("on_focus for window {id}: {command}");
+            continue;
+        }
+
+        let spawned = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("MYNIRI_WINDOW_ID", id.to_string())
+            .env(
+                "MYNIRI_APP_ID",
+                window.app_id.as_deref().unwrap_or_default(),
+            )
+            .env("MYNIRI_TITLE", window.title.as_deref().unwrap_or_default())
+            .stdin(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(child) => running = Some(child),
+            Err(e) => eprintln!("failed to run the on_focus hook: {e}"),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::{Context, Result, ensure};
+use niri_ipc::{Action, Window};
+use std::{
+    io::{IsTerminal, Read, Write, stdin, stdout},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    Direction,
+    config::Config,
+    ipc::{Ipc, Niri},
+    pick_line, snap_floating,
+};
+
+/// A key pressed in the picker.
+enum Key {
+    Up,
+    Down,
+    Enter,
+    Quit,
+    Char(char),
+    Other,
+}
+
+/// Let the user pick a window in the terminal and act on it, or only list the windows if this is
+/// not running in a terminal.
+pub fn run(niri: &mut Niri<impl Ipc>, snap: &Direction, config: &Config) -> Result<()> {
+    if !stdin().is_terminal() || !stdout().is_terminal() {
+        for window in windows(niri)? {
+            println!("{}", pick_line(&window));
+        }
+        return Ok(());
+    }
+
+    let _terminal = RawTerminal::enable()?;
+    let mut selected = 0;
+    let mut message = String::new();
+
+    loop {
+        // The windows are queried again every time, since the keys can change them.
+        let windows = windows(niri)?;
+        if windows.is_empty() {
+            return Ok(());
+        }
+        selected = selected.min(windows.len() - 1);
+        draw(&windows, selected, &message)?;
+        message.clear();
+
+        let window = &windows[selected];
+        match read_key()? {
+            Key::Up | Key::Char('k') => selected = selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => selected = (selected + 1).min(windows.len() - 1),
+            Key::Enter => {
+                niri.action(Action::FocusWindow { id: window.id })?;
+                return Ok(());
+            }
+            Key::Char('f') => niri.action(Action::ToggleWindowFloating {
+                id: Some(window.id),
+            })?,
+            Key::Char('s') if window.is_floating => {
+                snap_floating(niri, window, std::slice::from_ref(snap), config)?;
+            }
+            Key::Char('s') => message = "only floating windows can be snapped".to_string(),
+            Key::Quit | Key::Char('q') => return Ok(()),
+            Key::Char(_) | Key::Other => (),
+        }
+    }
+}
+
+fn windows(niri: &mut Niri<impl Ipc>) -> Result<Vec<Window>> {
+    let mut windows = niri.windows()?;
+    windows.sort_by_key(|w| w.id);
+    Ok(windows)
+}
+
+fn draw(windows: &[Window], selected: usize, message: &str) -> Result<()> {
+    let mut out = stdout().lock();
+
+    // The terminal doesn't translate newlines in raw mode.
+    write!(out, "\x1b[H\x1b[2J")?;
+    write!(
+        out,
+        "enter: focus, f: toggle floating, s: snap, q: quit\r\n\r\n"
+    )?;
+    for (i, window) in windows.iter().enumerate() {
+        let line = pick_line(window).replace('\t', "  ");
+        let kind = if window.is_floating {
+            "floating"
+        } else {
+            "tiled"
+        };
+        if i == selected {
+            write!(out, "\x1b[7m> {line} ({kind})\x1b[0m\r\n")?;
+        } else {
+            write!(out, "  {line} ({kind})\r\n")?;
+        }
+    }
+    if !message.is_empty() {
+        write!(out, "\r\n{message}\r\n")?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn read_key() -> Result<Key> {
+    // The escape sequence of an arrow key arrives in a single read, unlike a lone escape.
+    let mut buf = [0; 8];
+    let len = stdin().read(&mut buf)?;
+
+    Ok(match &buf[..len] {
+        b"\x1b[A" | b"\x1bOA" => Key::Up,
+        b"\x1b[B" | b"\x1bOB" => Key::Down,
+        b"\r" | b"\n" => Key::Enter,
+        // Escape, ctrl-c and ctrl-d, since raw mode turns off the signals.
+        [] | b"\x1b" | b"\x03" | b"\x04" => Key::Quit,
+        &[c] if c.is_ascii() => Key::Char(c as char),
+        _ => Key::Other,
+    })
+}
+
+/// The terminal in raw mode on the alternate screen, restored on drop.
+///
+/// This uses stty rather than a terminal library, as the picker only needs single key presses.
+struct RawTerminal {
+    /// The settings from before, as printed by `stty -g`
+    saved: String,
+}
+
+impl RawTerminal {
+    fn enable() -> Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+
+        print!("\x1b[?1049h\x1b[?25l");
+        stdout().flush()?;
+
+        Ok(Self {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Run stty on the terminal of stdin and return its output.
+fn stty(args: &[&str]) -> Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .context("failed to run stty")?;
+    ensure!(
+        output.status.success(),
+        "stty failed ({}): {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,631 @@
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use niri_ipc::{
+    Action, Event, Output, PositionChange, Reply, Request, Response, SizeChange, Window, Workspace,
+    socket::SOCKET_PATH_ENV,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, BufRead, BufReader, Write},
+    net::Shutdown,
+    ops::ControlFlow,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use crate::{GlobalArgs, SnapTo, config::Config, error::Failure, history};
+
+/// Something that answers niri requests, normally the niri socket.
+pub trait Ipc {
+    fn send(&mut self, request: Request) -> Result<Response>;
+}
+
+impl<T: Ipc + ?Sized> Ipc for &mut T {
+    fn send(&mut self, request: Request) -> Result<Response> {
+        (**self).send(request)
+    }
+}
+
+/// Connection to the niri socket.
+///
+/// This is [`niri_ipc::socket::Socket`] with timeouts, which it doesn't allow setting.
+pub struct Socket {
+    stream: BufReader<UnixStream>,
+}
+
+impl Socket {
+    fn connect_to(path: &Path, timeout: Option<Duration>) -> io::Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+
+        Ok(Self {
+            stream: BufReader::new(stream),
+        })
+    }
+
+    /// Read events after requesting an event stream, blocking until the next one arrives.
+    fn read_events(self) -> impl FnMut() -> Result<Event> {
+        let Self { mut stream } = self;
+        let _ = stream.get_mut().shutdown(Shutdown::Write);
+        // There can be any amount of time between events.
+        let _ = stream.get_mut().set_read_timeout(None);
+
+        let mut buf = String::new();
+        move || {
+            buf.clear();
+            stream.read_line(&mut buf)?;
+            Ok(serde_json::from_str(&buf)?)
+        }
+    }
+}
+
+impl Ipc for Socket {
+    fn send(&mut self, request: Request) -> Result<Response> {
+        let mut buf = serde_json::to_string(&request)?;
+        buf.push('\n');
+
+        let result = self
+            .stream
+            .get_mut()
+            .write_all(buf.as_bytes())
+            .and_then(|()| {
+                buf.clear();
+                self.stream.read_line(&mut buf)
+            });
+        match result {
+            Ok(_) => (),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                bail!("timed out waiting for niri response")
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        let reply: Reply = serde_json::from_str(&buf)?;
+        reply.map_err(|e| anyhow!("{e}"))
+    }
+}
+
+/// Connection to niri shared by all subcommands.
+pub struct Niri<S = Socket> {
+    socket: S,
+    /// Print actions to stderr instead of sending them
+    dry_run: bool,
+    /// Also print how the printed actions would change windows
+    diff: bool,
+    /// Window to act on instead of the focused one
+    window_id: Option<u64>,
+    /// Output to position windows on instead of the focused one
+    output: Option<String>,
+    /// How to choose the output to position a window on otherwise
+    snap_to: Option<SnapTo>,
+    /// Config to read the length of the history from
+    config: Option<PathBuf>,
+    /// Record the position of floating windows before moving them, for `undo`
+    history: bool,
+    /// Length of the history, read from the config on the first move
+    history_limit: Option<usize>,
+    /// Windows whose position was recorded for the current command already
+    recorded: HashSet<u64>,
+    /// Positions recorded for the current command that aren't in the history file yet
+    unsaved: Vec<history::Entry>,
+}
+
+/// Delay before reconnecting after the connection to niri was lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Oldest niri version, as `(year, month)`, that has all the actions used by myniri.
+const MIN_NIRI_VERSION: (u32, u32) = (25, 8);
+
+impl Niri {
+    /// Connect to niri and check that it is recent enough.
+    pub fn connect(args: &GlobalArgs) -> Result<Self> {
+        let mut niri = Self::new(connect(args).context(Failure::Connection)?, args);
+        niri.history = true;
+
+        let version = niri.version()?;
+        if let Some(parsed) = parse_version(&version)
+            && parsed < MIN_NIRI_VERSION
+        {
+            let (year, month) = MIN_NIRI_VERSION;
+            let message =
+                format!("niri {version} is older than {year}.{month:02}, which myniri requires");
+            ensure!(!args.strict, message);
+            eprintln!("warning: {message}");
+        }
+
+        Ok(niri)
+    }
+
+    /// Turn the connection into a stream of events.
+    ///
+    /// The returned function blocks until the next event arrives.
+    pub fn into_events(mut self) -> Result<impl FnMut() -> Result<Event>> {
+        self.request(Request::EventStream)?;
+
+        let mut read_event = self.socket.read_events();
+        Ok(move || read_event().context("reading an event"))
+    }
+
+    /// Run `handle` for every event with a connection for other requests, until it breaks or
+    /// fails.
+    ///
+    /// Whenever the connection drops, this connects again after a delay. The event stream starts
+    /// with the full state again then, so `handle` can keep its own state.
+    pub fn for_each_event_reconnecting(
+        args: &GlobalArgs,
+        mut handle: impl FnMut(&mut Self, Event) -> Result<ControlFlow<()>>,
+    ) -> Result<()> {
+        loop {
+            match Self::for_each_event(args, &mut handle) {
+                Ok(handled) => return handled,
+                Err(e) => eprintln!("lost connection to niri: {e:#}"),
+            }
+            thread::sleep(RECONNECT_DELAY);
+        }
+    }
+
+    /// Run `handle` for every event, failing only if the connection failed and returning what
+    /// `handle` ended with otherwise.
+    fn for_each_event(
+        args: &GlobalArgs,
+        handle: &mut impl FnMut(&mut Self, Event) -> Result<ControlFlow<()>>,
+    ) -> Result<Result<()>> {
+        let mut niri = Self::connect(args)?;
+        // Undoing should go back before the last command, not before a move made on its own.
+        niri.history = false;
+        let mut read_event = Self::connect(args)?.into_events()?;
+
+        loop {
+            match handle(&mut niri, read_event()?) {
+                Ok(ControlFlow::Continue(())) => (),
+                Ok(ControlFlow::Break(())) => return Ok(Ok(())),
+                Err(e) => return Ok(Err(e)),
+            }
+        }
+    }
+}
+
+impl<S: Ipc> Niri<S> {
+    pub fn new(socket: S, args: &GlobalArgs) -> Self {
+        let mut niri = Self {
+            socket,
+            dry_run: false,
+            diff: false,
+            window_id: None,
+            output: None,
+            snap_to: None,
+            config: None,
+            history: false,
+            history_limit: None,
+            recorded: HashSet::new(),
+            unsaved: Vec::new(),
+        };
+        niri.configure(args);
+        niri
+    }
+
+    /// Apply the options in `args` that don't affect the connection itself.
+    pub fn configure(&mut self, args: &GlobalArgs) {
+        self.dry_run = args.dry_run;
+        self.diff = args.diff;
+        self.window_id = args.window_id;
+        self.output = args.output.clone();
+        self.snap_to = args.snap_to;
+        if self.config != args.config {
+            self.config = args.config.clone();
+            self.history_limit = None;
+        }
+        self.save_history();
+        self.recorded.clear();
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Send a request and return niri's response.
+    ///
+    /// This is sent even in dry-run mode, so it must not be used for actions.
+    pub fn request(&mut self, request: Request) -> Result<Response> {
+        log::debug!("sending {request:?}");
+        let response = self
+            .socket
+            .send(request.clone())
+            .with_context(|| format!("sending {request:?}"));
+        log::trace!("received {response:?}");
+        response
+    }
+
+    /// Run an action, or only print it in dry-run mode.
+    ///
+    /// A floating window that the action moves has its position recorded for `undo` first.
+    pub fn action(&mut self, action: Action) -> Result<()> {
+        // The history is only a convenience, so it never prevents the action.
+        if self.history
+            && !self.dry_run
+            && let Err(e) = self.remember_position(&action)
+        {
+            eprintln!("warning: failed to record the window position: {e:#}");
+        }
+        self.action_unrecorded(action)
+    }
+
+    /// Like [`Self::action`], but without recording the position of a moved window.
+    pub fn action_unrecorded(&mut self, action: Action) -> Result<()> {
+        let request = Request::Action(action);
+        log::info!("running {request:?}");
+
+        if self.dry_run {
+            eprintln!("{request:?}");
+            if self.diff
+                && let Request::Action(action) = &request
+            {
+                self.print_diff(action)?;
+            }
+            return Ok(());
+        }
+
+        self.request(request)?;
+        Ok(())
+    }
+
+    /// Append the positions recorded since the last call to the history file.
+    ///
+    /// This is done once per command, as it rewrites the whole file.
+    pub fn save_history(&mut self) {
+        if self.unsaved.is_empty() {
+            return;
+        }
+        let entries = std::mem::take(&mut self.unsaved);
+        if let Err(e) = self
+            .history_limit()
+            .and_then(|limit| history::record(entries, limit))
+        {
+            eprintln!("warning: failed to record the window positions: {e:#}");
+        }
+    }
+
+    /// Record the position of the floating window that `action` moves for the history, once per
+    /// command so that undoing goes back to where the window was before the command.
+    fn remember_position(&mut self, action: &Action) -> Result<()> {
+        let id = match action {
+            Action::MoveFloatingWindow { id, .. } | Action::MoveWindowToMonitor { id, .. } => *id,
+            _ => return Ok(()),
+        };
+        if id.is_some_and(|id| self.recorded.contains(&id)) || self.history_limit()? == 0 {
+            return Ok(());
+        }
+        let window = self.window_with_id(id)?;
+        if !window.is_floating || !self.recorded.insert(window.id) {
+            return Ok(());
+        }
+
+        let output = self.current_output(&window)?;
+        if let Some((x, y)) = crate::absolute_position(&window, &output) {
+            self.unsaved.push(history::Entry {
+                id: window.id,
+                x,
+                y,
+            });
+        }
+        Ok(())
+    }
+
+    fn history_limit(&mut self) -> Result<usize> {
+        if let Some(limit) = self.history_limit {
+            return Ok(limit);
+        }
+        let limit = Config::load(self.config.as_deref())?.history();
+        Ok(*self.history_limit.insert(limit))
+    }
+
+    /// Print the current position or size of the window that `action` moves or resizes next to
+    /// the new one, for `--diff`.
+    fn print_diff(&mut self, action: &Action) -> Result<()> {
+        let id = match action {
+            Action::MoveFloatingWindow { id, .. }
+            | Action::SetWindowWidth { id, .. }
+            | Action::SetWindowHeight { id, .. } => *id,
+            _ => return Ok(()),
+        };
+        let window = self.window_with_id(id)?;
+
+        let (width, height) = window.layout.window_size;
+        let diff = match action {
+            Action::MoveFloatingWindow { x, y, .. } => {
+                let output = self.current_output(&window)?;
+                let Some((current_x, current_y)) = crate::absolute_position(&window, &output)
+                else {
+                    bail!("the window {} has no position", window.id)
+                };
+                let moved = |current: f64, change: &PositionChange| match *change {
+                    PositionChange::SetFixed(pos) => pos,
+                    PositionChange::AdjustFixed(delta) => current + delta,
+                };
+                format!(
+                    "x {current_x} -> {}, y {current_y} -> {}",
+                    moved(current_x, x),
+                    moved(current_y, y)
+                )
+            }
+            Action::SetWindowWidth { change, .. } => {
+                format!("width {width} -> {}", resized(width, change))
+            }
+            Action::SetWindowHeight { change, .. } => {
+                format!("height {height} -> {}", resized(height, change))
+            }
+            _ => unreachable!(),
+        };
+        eprintln!("  window {}: {diff}", window.id);
+
+        Ok(())
+    }
+
+    pub fn version(&mut self) -> Result<String> {
+        match self.request(Request::Version)? {
+            Response::Version(version) => Ok(version),
+            response => bail!("unexpected response to Version: {response:?}"),
+        }
+    }
+
+    pub fn focused_window(&mut self) -> Result<Window> {
+        match self.request(Request::FocusedWindow)? {
+            Response::FocusedWindow(Some(window)) => Ok(window),
+            Response::FocusedWindow(None) => Err(Failure::NoFocusedWindow.into()),
+            response => bail!("unexpected response to FocusedWindow: {response:?}"),
+        }
+    }
+
+    /// The window to act on: the one given with `--window-id`, or else the focused one.
+    pub fn window(&mut self) -> Result<Window> {
+        self.window_with_id(self.window_id)
+    }
+
+    /// The window with `id`, or the focused one without an id like in actions.
+    fn window_with_id(&mut self, id: Option<u64>) -> Result<Window> {
+        match id {
+            Some(id) => self
+                .windows()?
+                .into_iter()
+                .find(|w| w.id == id)
+                .ok_or_else(|| anyhow!("there is no window with id {id}")),
+            None => self.focused_window(),
+        }
+    }
+
+    /// Like [`Self::window`], but fails if the window is floating.
+    pub fn tiled_window(&mut self) -> Result<Window> {
+        let window = self.window()?;
+        ensure!(!window.is_floating, "the window is floating, not tiled");
+        Ok(window)
+    }
+
+    /// Like [`Self::window`], but fails if the window is tiled.
+    pub fn floating_window(&mut self) -> Result<Window> {
+        let window = self.window()?;
+        ensure!(window.is_floating, Failure::NotFloating);
+        Ok(window)
+    }
+
+    pub fn focused_output(&mut self) -> Result<Output> {
+        match self.request(Request::FocusedOutput)? {
+            Response::FocusedOutput(Some(output)) => Ok(output),
+            Response::FocusedOutput(None) => bail!("no output is focused"),
+            response => bail!("unexpected response to FocusedOutput: {response:?}"),
+        }
+    }
+
+    pub fn windows(&mut self) -> Result<Vec<Window>> {
+        match self.request(Request::Windows)? {
+            Response::Windows(windows) => Ok(windows),
+            response => bail!("unexpected response to Windows: {response:?}"),
+        }
+    }
+
+    /// The output to position windows on: the one given with `--output`, or else the focused one.
+    pub fn output(&mut self) -> Result<Output> {
+        match self.output.clone() {
+            Some(name) => self
+                .outputs()?
+                .remove(&name)
+                .ok_or_else(|| anyhow!("there is no output named {name}")),
+            None => self.focused_output(),
+        }
+    }
+
+    /// The output to position `window` on: the one given with `--output`, or else the one chosen
+    /// with `--snap-to`.
+    ///
+    /// With `--snap-to window` this is the output containing the center of the window, which can
+    /// differ from [`Self::current_output`] when the window reaches over to another output.
+    pub fn target_output(&mut self, window: &Window) -> Result<Output> {
+        if self.output.is_some() || self.snap_to != Some(SnapTo::Window) {
+            return self.output();
+        }
+
+        let Some(current) = self.workspace_output(window)? else {
+            return self.focused_output();
+        };
+
+        let mut outputs = self.outputs()?;
+        let center = current
+            .logical
+            .zip(window.layout.tile_pos_in_workspace_view)
+            .map(|(logical, (x, y))| {
+                let (width, height) = window.layout.tile_size;
+                (
+                    logical.x as f64 + x + width / 2.,
+                    logical.y as f64 + y + height / 2.,
+                )
+            });
+        let containing = center.and_then(|(x, y)| {
+            outputs.values().find(|output| {
+                output.logical.is_some_and(|l| {
+                    (l.x as f64..l.x as f64 + l.width as f64).contains(&x)
+                        && (l.y as f64..l.y as f64 + l.height as f64).contains(&y)
+                })
+            })
+        });
+
+        let name = containing.map(|output| output.name.clone());
+        Ok(name
+            .and_then(|name| outputs.remove(&name))
+            .unwrap_or(current))
+    }
+
+    /// The output showing the workspace of `window`, which the position of the window is relative
+    /// to.
+    pub fn current_output(&mut self, window: &Window) -> Result<Output> {
+        self.workspace_output(window)?
+            .ok_or_else(|| anyhow!("the window is not on any output"))
+    }
+
+    fn workspace_output(&mut self, window: &Window) -> Result<Option<Output>> {
+        let name = self
+            .workspaces()?
+            .into_iter()
+            .find(|ws| Some(ws.id) == window.workspace_id)
+            .and_then(|ws| ws.output);
+        let Some(name) = name else {
+            return Ok(None);
+        };
+
+        Ok(self.outputs()?.remove(&name))
+    }
+
+    pub fn outputs(&mut self) -> Result<HashMap<String, Output>> {
+        match self.request(Request::Outputs)? {
+            Response::Outputs(outputs) => Ok(outputs),
+            response => bail!("unexpected response to Outputs: {response:?}"),
+        }
+    }
+
+    pub fn workspaces(&mut self) -> Result<Vec<Workspace>> {
+        match self.request(Request::Workspaces)? {
+            Response::Workspaces(workspaces) => Ok(workspaces),
+            response => bail!("unexpected response to Workspaces: {response:?}"),
+        }
+    }
+}
+
+/// The size `change` sets a window that is `current` pixels wide or high to, as text since sizes
+/// in proportions depend on the working area.
+fn resized(current: i32, change: &SizeChange) -> String {
+    match *change {
+        SizeChange::SetFixed(size) => size.to_string(),
+        SizeChange::AdjustFixed(delta) => (current + delta).to_string(),
+        SizeChange::SetProportion(pct) => format!("{pct}%"),
+        SizeChange::AdjustProportion(pct) => format!("{current} {pct:+}%"),
+    }
+}
+
+/// Parse the `year.month` at the start of a niri version like `25.08 (1234abc)`.
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let version = version.split_whitespace().next()?;
+    let mut parts = version.split(['.', '-']);
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    Some((year, month))
+}
+
+/// Delay before the first retry of a failed connection, doubled for each further retry.
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Connect to the socket given with `--socket`, or else to the one in `$NIRI_SOCKET`, retrying as
+/// many times as `--retry` says.
+fn connect(args: &GlobalArgs) -> Result<Socket> {
+    let mut delay = RETRY_DELAY;
+
+    for _ in 0..args.retry {
+        match connect_once(args) {
+            Ok(socket) => return Ok(socket),
+            Err(e) => {
+                eprintln!("failed to connect to niri, retrying in {delay:?}: {e:#}");
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+    }
+
+    connect_once(args)
+}
+
+fn connect_once(args: &GlobalArgs) -> Result<Socket> {
+    let path = match &args.socket {
+        Some(path) => path.clone(),
+        None => std::env::var_os(SOCKET_PATH_ENV)
+            .ok_or_else(|| {
+                anyhow!("{SOCKET_PATH_ENV} is not set, are you running this within niri?")
+            })?
+            .into(),
+    };
+    let timeout = args.timeout.map(Duration::from_millis);
+
+    Socket::connect_to(&path, timeout)
+        .with_context(|| format!("failed to connect to {}", path.display()))
+}
+
+#[cfg(test)]
+pub mod mock {
+    use anyhow::{Result, bail};
+    use niri_ipc::{Action, Output, Request, Response, Window, Workspace};
+
+    /// Answers queries with canned responses and records the actions it receives.
+    #[derive(Default)]
+    pub struct Mock {
+        pub windows: Vec<Window>,
+        /// Replaces `windows` once the first action arrives, for commands that look at the layout
+        /// their actions made
+        pub windows_after_action: Option<Vec<Window>>,
+        pub workspaces: Vec<Workspace>,
+        pub output: Option<Output>,
+        pub actions: Vec<Action>,
+    }
+
+    impl super::Ipc for Mock {
+        fn send(&mut self, request: Request) -> Result<Response> {
+            Ok(match request {
+                Request::FocusedWindow => {
+                    Response::FocusedWindow(self.windows.iter().find(|w| w.is_focused).cloned())
+                }
+                Request::FocusedOutput => Response::FocusedOutput(self.output.clone()),
+                Request::Outputs => Response::Outputs(
+                    self.output
+                        .iter()
+                        .map(|output| (output.name.clone(), output.clone()))
+                        .collect(),
+                ),
+                Request::Windows => Response::Windows(self.windows.clone()),
+                Request::Workspaces => Response::Workspaces(self.workspaces.clone()),
+                Request::Action(action) => {
+                    if let Some(windows) = self.windows_after_action.take() {
+                        self.windows = windows;
+                    }
+                    self.actions.push(action);
+                    Response::Handled
+                }
+                request => bail!("unexpected request {request:?}"),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_version;
+
+    #[test]
+    fn versions() {
+        assert_eq!(parse_version("25.08 (v25.08)"), Some((25, 8)));
+        assert_eq!(parse_version("25.11-12-gabc1234"), Some((25, 11)));
+        assert_eq!(parse_version("unknown"), None);
+        assert_eq!(parse_version(""), None);
+    }
+}
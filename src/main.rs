@@ -1,166 +1,3992 @@
-use anyhow::{Result, anyhow, bail, ensure};
-use clap::{Parser, Subcommand, ValueEnum};
-use niri_ipc::{Action, PositionChange, Request, socket::Socket};
-use std::process::Stdio;
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use config::Config;
+use error::Failure;
+use ipc::{Ipc, Niri};
+use niri_ipc::{
+    Action, ConfiguredMode, Event, LayoutSwitchTarget, ModeToSet, Output, OutputAction,
+    PositionChange, Request, Response, ScaleToSet, SizeChange, Window, WorkspaceReferenceArg,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process::{ExitCode, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+mod config;
+mod error;
+mod follow;
+mod history;
+mod hooks;
+mod interactive;
+mod ipc;
+mod state;
+mod status;
+mod sticky;
+mod watch;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(
+    after_help = "Exit codes: 0 on success, 2 if no window is focused, 3 if the window is not \
+floating but has to be, 4 if niri is not reachable and 1 for any other error."
+)]
 struct Args {
+    #[command(flatten)]
+    global: GlobalArgs,
     #[command(subcommand)]
     command: Command,
 }
 
+#[derive(clap::Args, Debug, Clone, Default)]
+struct GlobalArgs {
+    /// Path to the config file [default: ~/.config/myniri/config.toml]
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Print the actions to stderr instead of sending them to niri
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// With --dry-run, also print how the actions would change the position and size of windows
+    #[arg(long, global = true, requires = "dry_run")]
+    diff: bool,
+    /// Act on the window with this id instead of the focused window
+    #[arg(long, global = true)]
+    window_id: Option<u64>,
+    /// Path to the niri socket [default: $NIRI_SOCKET]
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+    /// Position windows relative to the output with this name instead of the focused output
+    #[arg(long, global = true)]
+    output: Option<String>,
+    /// Margin replacing the configured one on every output, like `bottom=48` or `left=5%`
+    ///
+    /// Can be repeated for different sides. Takes precedence over `$MYNIRI_MARGIN_LEFT`,
+    /// `$MYNIRI_MARGIN_RIGHT`, `$MYNIRI_MARGIN_TOP` and `$MYNIRI_MARGIN_BOTTOM`, which take
+    /// precedence over the config.
+    #[arg(long = "margin", global = true, value_name = "SIDE=MARGIN", value_parser = config::parse_margin_arg)]
+    margins: Vec<(config::MarginSide, config::Margin)>,
+    /// Which output to position a window on when `--output` is not given [default: focus]
+    #[arg(long, global = true, value_enum)]
+    snap_to: Option<SnapTo>,
+    /// Retry connecting to niri this many times, waiting longer after each attempt
+    #[arg(long, global = true, default_value_t = 0)]
+    retry: u32,
+    /// Fail instead of warning when the running niri is older than supported
+    #[arg(long, global = true)]
+    strict: bool,
+    /// Give up on niri after waiting this many milliseconds for a response
+    #[arg(long, global = true)]
+    timeout: Option<u64>,
+    /// Log what is sent to and received from niri, repeat for more details
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+impl GlobalArgs {
+    /// These options, with the ones that were not given taken from `defaults`.
+    fn or(self, defaults: &GlobalArgs) -> GlobalArgs {
+        GlobalArgs {
+            config: self.config.or_else(|| defaults.config.clone()),
+            dry_run: self.dry_run || defaults.dry_run,
+            diff: self.diff || defaults.diff,
+            window_id: self.window_id.or(defaults.window_id),
+            socket: self.socket.or_else(|| defaults.socket.clone()),
+            output: self.output.or_else(|| defaults.output.clone()),
+            snap_to: self.snap_to.or(defaults.snap_to),
+            margins: if self.margins.is_empty() {
+                defaults.margins.clone()
+            } else {
+                self.margins
+            },
+            retry: self.retry.max(defaults.retry),
+            strict: self.strict || defaults.strict,
+            timeout: self.timeout.or(defaults.timeout),
+            verbose: self.verbose.max(defaults.verbose),
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Command {
     /// Snap (move) floating windows by given direction or run given action.
     FloatingSnapOr {
         /// Direction to move the floating window
-        #[arg(short, long, value_parser)]
-        direction: Direction,
+        ///
+        /// Can be repeated to combine directions that change different axes, like `--direction
+        /// left --direction up` for the top left corner.
+        #[arg(short, long, value_parser, required = true)]
+        direction: Vec<Direction>,
+        /// Align the window with the margins instead of its tile
+        ///
+        /// The tile is the window with the border around it, so this puts the border inside the
+        /// margins. Shadows are drawn outside the tile and are never taken into account. Can also
+        /// be set with `account_border = true` in the config.
+        #[arg(long)]
+        account_border: bool,
         /// If the focusing window is not floating, then run this action
+        ///
+        /// Defaults to the action for the direction in the `[or_action]` table of the config.
+        /// Without either, nothing is done for windows that are not floating.
+        #[command(subcommand)]
+        or_action: Option<Action>,
+    },
+    /// Open or close the overview.
+    ToggleOverview,
+    /// Open the overview if there are at least two workspaces with windows, or run given action.
+    ///
+    /// An open overview is always closed.
+    ToggleOverviewOr {
+        /// If there are not enough workspaces to pick from, then run this action
+        #[command(subcommand)]
+        or_action: Action,
+    },
+    /// Toggle fullscreen on the focused window if it is tiled, or run given action.
+    FullscreenOr {
+        /// If the window is floating, then run this action
         #[command(subcommand)]
         or_action: Action,
     },
     /// Toggle window follow mode, only when the focusing window is floating.
     ///
-    /// This subcommand requires nirius
-    ToggleFollowMode,
-    ConsumeIntoLeft,
+    /// This uses nirius if it is installed. Otherwise, or with `--native`, the window follows the
+    /// focus as long as `myniri follow` runs.
+    ToggleFollowMode {
+        /// Don't use nirius even if it is installed
+        #[arg(long)]
+        native: bool,
+        /// Path or name in `$PATH` of the nirius binary [default: `nirius_path` in the config, or
+        /// else nirius]
+        #[arg(long, conflicts_with = "native")]
+        nirius_bin: Option<PathBuf>,
+    },
+    ConsumeIntoLeft {
+        #[command(flatten)]
+        consume: ConsumeArgs,
+    },
+    ConsumeIntoRight {
+        #[command(flatten)]
+        consume: ConsumeArgs,
+    },
+    /// Swap the column of the focused tiled window with the column on its left.
+    SwapColumnLeft {
+        #[command(flatten)]
+        focus: FocusArgs,
+    },
+    /// Swap the column of the focused tiled window with the column on its right.
+    SwapColumnRight {
+        #[command(flatten)]
+        focus: FocusArgs,
+    },
+    /// Set the width of the column of the focused tiled window to one of the `column_widths` in the
+    /// config.
+    ///
+    /// Without a preset, the one after the preset closest to the current width is used.
+    ColumnWidth {
+        /// Number of the preset, starting from 1
+        preset: Option<usize>,
+    },
+    /// Give all columns of the focused workspace the same width, keeping the focus where it was.
+    BalanceColumns,
+    /// Move the focused tiled window out of its column into a new column next to it.
+    EjectWindow {
+        /// Side of the column to put the new column on, left or right
+        #[arg(short, long, value_parser)]
+        direction: Direction,
+        #[command(flatten)]
+        focus: FocusArgs,
+    },
+    /// Toggle the focused window between floating and tiled.
+    ToggleFloating {
+        /// If the window becomes floating, snap it to this direction
+        #[arg(short, long, value_parser)]
+        direction: Option<Direction>,
+    },
+    /// Move the focused floating window by a number of pixels.
+    FloatingNudge {
+        /// Direction to move the floating window
+        #[arg(short, long, value_parser)]
+        direction: Direction,
+        /// Distance to move the window in logical pixels
+        #[arg(short, long, allow_negative_numbers = true)]
+        amount: f64,
+        /// Move the window this many times
+        #[arg(long, default_value_t = 1)]
+        repeat: u32,
+        /// Double the distance with each move, and keep doubling it across nudges of the same
+        /// window in the same direction in quick succession, like from a held key
+        #[arg(long)]
+        accel: bool,
+    },
+    /// Resize the focused floating window to a percentage of the focused output.
+    FloatingResize {
+        /// Width in percent of the output width, unchanged if omitted
+        #[arg(long)]
+        width_pct: Option<f64>,
+        /// Height in percent of the output height, unchanged if omitted
+        #[arg(long)]
+        height_pct: Option<f64>,
+    },
+    /// Resize and move the focused floating window to fill one half of the focused output.
+    FloatingHalf {
+        /// Half of the output to fill, one of left, down, up or right
+        #[arg(short, long, value_parser)]
+        side: Direction,
+    },
+    /// Resize and move the focused floating window to fill one quarter of the focused output.
+    FloatingQuarter {
+        /// Quarter of the output to fill
+        #[arg(short, long, value_parser)]
+        corner: Corner,
+    },
+    /// Resize and move the focused floating window to fill one cell of a grid on the focused output.
+    FloatingGrid {
+        /// Number of columns of the grid
+        #[arg(long)]
+        cols: u32,
+        /// Number of rows of the grid
+        #[arg(long)]
+        rows: u32,
+        /// Cell to fill, from 1, numbered left to right and then top to bottom
+        #[arg(long)]
+        cell: u32,
+    },
+    /// Resize and move the focused floating window to fill its output, or restore it.
+    ///
+    /// The geometry from before maximizing is kept in a state file, so running this again on the
+    /// same window restores it.
+    FloatingMaximize,
+    /// Resize the focused floating window to the largest size with the given aspect ratio that
+    /// fits the focused output, and center it.
+    FloatingAspect {
+        /// Width divided by height, like 1.777 for 16:9
+        ratio: f64,
+    },
+    /// Make the focused floating window wider and higher by the given amount, keeping its center
+    /// where it is.
+    FloatingGrow {
+        /// Pixels to add to the width and the height, negative to shrink the window
+        #[arg(allow_negative_numbers = true)]
+        amount: f64,
+    },
+    /// Run a program and snap its window once it opens, making it floating if needed.
+    ///
+    /// The window is recognized by the pid of the program, which doesn't work for programs that
+    /// hand off to another process, so `--app-id` can be given as well.
+    SpawnFloating {
+        /// Direction to snap the window to
+        #[arg(short, long, value_parser)]
+        snap: Direction,
+        /// Also accept a new window with this app id
+        #[arg(long)]
+        app_id: Option<String>,
+        /// Seconds to wait for the window to open
+        #[arg(long, default_value_t = 10)]
+        open_timeout: u64,
+        /// Program to run and its arguments
+        #[arg(required = true, trailing_var_arg = true)]
+        cmd: Vec<String>,
+    },
+    /// Move the floating windows on the focused output next to each other so that they don't
+    /// overlap.
+    ///
+    /// The windows are placed in rows from the top left of the output by increasing id, so that
+    /// running this again gives the same result.
+    FloatingSpread,
+    /// Swap the positions of the focused floating window and another one, keeping their sizes.
+    FloatingSwap {
+        /// Id of the floating window to swap with
+        #[arg(long)]
+        with: u64,
+    },
+    /// Snap all floating windows on the focused output to the given direction.
+    ///
+    /// Each window is snapped on its own, so windows of different sizes line up along the edge.
+    FloatingSnapAll {
+        /// Direction to move the floating windows
+        #[arg(short, long, value_parser)]
+        direction: Direction,
+    },
+    /// Resize the floating windows on the focused output to the same size.
+    ///
+    /// The windows keep their top left corner unless `--reflow` is given.
+    FloatingEqualize {
+        /// Width of the windows in logical pixels, including the borders
+        #[arg(long)]
+        width: f64,
+        /// Height of the windows in logical pixels, including the borders
+        #[arg(long)]
+        height: f64,
+        /// Also move the windows next to each other like floating-spread
+        #[arg(long)]
+        reflow: bool,
+    },
+    /// Move the floating windows on the focused output into a cascade from the top left.
+    ///
+    /// The windows are placed by increasing id, each one further right and down than the last.
+    FloatingCascade {
+        /// Distance between the windows in logical pixels
+        #[arg(long, default_value_t = 32.)]
+        offset: f64,
+    },
+    /// Snap and resize the floating windows as given by a `[[layout]]` in the config.
+    ///
+    /// Entries for apps without a floating window are skipped.
+    Layout {
+        /// Name of the layout
+        name: String,
+    },
+    /// Hide the focused floating window on the stash workspace, like minimizing it.
+    ///
+    /// The workspace is named by `stash_workspace` in the config, "stash" by default, and has to
+    /// be declared in the niri config.
+    FloatingStash,
+    /// Bring the window stashed last back to the focused workspace, where it was before.
+    FloatingUnstash,
+    /// Print information about the focused window.
+    FocusedWindow {
+        /// Print the window as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List windows, optionally filtered.
+    Windows {
+        /// Only list windows with exactly this app id
+        #[arg(long)]
+        app_id: Option<String>,
+        /// Only list windows whose title contains this string
+        #[arg(long)]
+        title: Option<String>,
+        /// Print the windows as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Focus the first window with the given app id.
+    FocusApp { app_id: String },
+    /// Raise all floating windows with the given app id above the other floating windows.
+    ///
+    /// niri raises a floating window when it gets focused and has no other way to change the
+    /// stacking order, so the windows are focused one after the other by increasing id, leaving
+    /// the last one focused and on top.
+    FloatingRaise { app_id: String },
+    /// Focus the first window with the given app id, or run a program if there is none.
+    FocusOrSpawn {
+        app_id: String,
+        /// Wait this many seconds for a window with the app id to open and focus it
+        #[arg(long)]
+        wait: Option<u64>,
+        /// Program to run and its arguments
+        #[arg(required = true, trailing_var_arg = true)]
+        cmd: Vec<String>,
+    },
+    /// Move all windows with the given app id to the focused workspace.
+    GatherApp {
+        app_id: String,
+        /// Also move the gathered windows that are floating to the tiling layout
+        #[arg(long)]
+        tile: bool,
+    },
+    /// Close all windows with the given app id.
+    ///
+    /// With `--dry-run` the windows that would be closed are printed instead.
+    CloseApp { app_id: String },
+    /// Focus the first window whose title contains the given pattern.
+    FocusTitle { pattern: String },
+    /// Focus the nearest floating window on the focused output in the given direction from the
+    /// focused window.
+    ///
+    /// Windows are compared by the centers of their tiles.
+    FocusFloating {
+        /// Direction to look for a window in
+        #[arg(short, long, value_parser)]
+        direction: Direction,
+    },
+    /// List windows for a menu program like fuzzel or rofi, or focus the window picked from it.
+    ///
+    /// For example `myniri pick | fuzzel --dmenu | myniri pick --focus-from-stdin`.
+    Pick {
+        /// Read a line printed by `pick` from stdin and focus its window
+        ///
+        /// Empty input, as given by a cancelled menu, does nothing.
+        #[arg(long)]
+        focus_from_stdin: bool,
+    },
+    /// Pick a window in the terminal with the arrow keys and focus it with enter.
+    ///
+    /// `f` toggles the picked window between floating and tiled and `s` snaps it. When not run in
+    /// a terminal, the windows are listed like with `pick` instead.
+    Interactive {
+        /// Direction `s` snaps floating windows to
+        #[arg(short, long, value_parser, default_value = "center")]
+        snap: Direction,
+    },
+    /// Pin the focused floating window, keeping it on every workspace that gets focused.
+    ///
+    /// niri has no such windows, so this puts the window in the follow mode of `myniri follow`,
+    /// which has to run for it to work.
+    FloatingPin,
+    /// Unpin the focused floating window, leaving it on its current workspace.
+    FloatingUnpin,
+    /// Move the windows in follow mode to each workspace that gets focused.
+    ///
+    /// Windows are put in follow mode with `toggle-follow-mode` or `floating-pin`. Keeps running
+    /// and reconnects to niri when the connection is lost.
+    Follow,
+    /// Snap floating windows according to the `[[rule]]`s in the config as they appear.
+    ///
+    /// Keeps running and reconnects to niri when the connection is lost.
+    Watch,
+    /// Move the focused window to the workspace with the given index on its output.
+    MoveToWorkspace {
+        index: u8,
+        #[command(flatten)]
+        focus: FocusArgs,
+    },
+    /// Move the focused window to a new workspace of its own on its output.
+    MoveToNewWorkspace {
+        #[command(flatten)]
+        focus: FocusArgs,
+    },
+    /// Move the focused floating window to the next position of the cycle in the config.
+    ///
+    /// The current position is the one of the cycle closest to the window.
+    FloatingCycle,
+    /// Place the focused floating window at a fraction of the free space of the focused output.
+    ///
+    /// 0 puts the window at the left or top margin, 100 at the right or bottom margin.
+    FloatingPlace {
+        /// Horizontal position in percent, unchanged if omitted
+        #[arg(long)]
+        x_pct: Option<f64>,
+        /// Vertical position in percent, unchanged if omitted
+        #[arg(long)]
+        y_pct: Option<f64>,
+    },
+    /// Print the geometry used for snapping the focused window, to find out why a snap landed
+    /// where it did.
+    ///
+    /// This shows the output, its margins in pixels, the work area inside them, and the position
+    /// each direction would snap the window to.
+    Debug {
+        /// Print the geometry as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List outputs with their geometry.
+    Outputs {
+        /// Print the outputs as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List workspaces with the number of windows on them.
+    Workspaces {
+        /// Print the workspaces as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Focus the output with the given name.
+    FocusMonitor {
+        /// Name of the output, like DP-1
+        name: String,
+    },
+    /// Move the focused window to the output with the given name.
+    MoveToMonitor {
+        /// Name of the output, like DP-1
+        name: String,
+        #[command(flatten)]
+        focus: FocusArgs,
+    },
+    /// Move the focused workspace with all its windows to the output with the given name.
+    WorkspaceToMonitor {
+        /// Name of the output, like DP-1
+        name: String,
+    },
+    /// Move the focused window to the next output from left to right.
+    ///
+    /// Floating windows keep their relative position like with floating-to-output.
+    CycleMonitors {
+        /// Go from the last output back to the first one
+        #[arg(short, long)]
+        wrap: bool,
+        #[command(flatten)]
+        focus: FocusArgs,
+    },
+    /// Move the focused floating window to another output, keeping its relative position.
+    FloatingToOutput {
+        /// Name of the output to move the window to
+        name: String,
+    },
+    /// Print the versions of myniri and of the running niri.
+    Version,
+    /// Run commands read from stdin over a single connection to niri.
+    ///
+    /// Each line of stdin is a command line without the program name, like `consume-into-left
+    /// --count 2`, and empty lines and lines starting with `#` are skipped. Global options given to
+    /// `run` apply to every line. Alternatively, stdin can be a JSON array of niri requests, whose
+    /// responses are printed as JSON.
+    Run {
+        /// Continue with the next line when a line fails
+        #[arg(long)]
+        keep_going: bool,
+    },
+    /// Move the floating window that was moved last back to where it was before.
+    ///
+    /// Every command that moves floating windows records where they were, up to the `history`
+    /// entries in the config.
+    Undo,
+    /// Keep the focused floating window snapped to a corner of its output.
+    ///
+    /// The window is snapped again whenever the geometry of its output changes, for example when
+    /// a monitor is plugged in or its mode or scale changes. Keeps running until the window is
+    /// closed and reconnects to niri when the connection is lost.
+    StickyCorner {
+        /// Corner to keep the window in
+        #[arg(short, long, value_parser)]
+        corner: Corner,
+    },
+    /// Print the focused workspace and window as JSON for a waybar custom module.
+    ///
+    /// A line is printed whenever they change, for use with `return-type: json`. Keeps running and
+    /// reconnects to niri when the connection is lost.
+    Status,
+    /// Run the `on_focus` command in the `[hooks]` of the config whenever a window gets focused.
+    ///
+    /// The command runs with `sh -c` once the focus stayed on the window for `debounce_ms`, and
+    /// not before the previous one finished. It gets the window in `$MYNIRI_WINDOW_ID`,
+    /// `$MYNIRI_APP_ID` and `$MYNIRI_TITLE`. Keeps running and reconnects to niri when the
+    /// connection is lost.
+    Hooks,
+    /// Send a niri request given as JSON and print the response as JSON.
+    ///
+    /// For example `'"Windows"'` or `'{"Action":{"FocusColumnLeft":{}}}'`. With --dry-run, actions
+    /// and output changes are only printed.
+    Raw { json: String },
+    /// Quit niri, ending the session.
+    ///
+    /// Without `--confirm` this asks first when run in a terminal, and fails otherwise.
+    Quit {
+        /// Quit without asking
+        #[arg(long)]
+        confirm: bool,
+    },
+    /// Switch the keyboard layout or print the configured layouts.
+    KbLayout {
+        #[command(subcommand)]
+        action: KbLayoutAction,
+    },
+    /// Turn an output on or off, or change its scale or mode.
+    Output {
+        /// Name of the output, like DP-1
+        name: String,
+        #[command(subcommand)]
+        action: OutputChange,
+    },
+    /// Print a shell completion script to stdout.
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
 }
 
-#[derive(Debug, Clone, ValueEnum)]
+#[derive(Subcommand, Debug)]
+enum KbLayoutAction {
+    /// Switch to the next layout
+    Next,
+    /// Switch to the previous layout
+    Prev,
+    /// Switch to the layout with this index, starting from 0
+    Index { index: u8 },
+    /// Print the layouts, marking the active one with `*`
+    Print,
+}
+
+#[derive(Subcommand, Debug)]
+enum OutputChange {
+    /// Turn the output on
+    On,
+    /// Turn the output off
+    Off,
+    /// Set the scale of the output
+    Scale { scale: f64 },
+    /// Set the mode of the output, one of those it has
+    Mode {
+        /// Mode as WIDTHxHEIGHT@REFRESH, like 1920x1080@60, where @REFRESH is optional
+        mode: String,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct ConsumeArgs {
+    /// Number of columns to move the window
+    #[arg(short, long, default_value_t = 1)]
+    count: usize,
+    /// Row of the destination column to move the window to
+    ///
+    /// Without this the window stays where niri puts it, which is the bottom of the column when
+    /// consuming into left.
+    #[arg(long, value_enum)]
+    row: Option<Row>,
+    /// Print what was done as JSON
+    #[arg(long)]
+    json: bool,
+    #[command(flatten)]
+    focus: FocusArgs,
+}
+
+/// Where the focus goes after moving a window, shared by the commands that move windows.
+#[derive(clap::Args, Debug, Clone, Copy)]
+struct FocusArgs {
+    /// Move the focus along with the window
+    #[arg(short, long, visible_alias = "follow", overrides_with = "focus_stays")]
+    follow_window: bool,
+    /// Keep the focus on the window that had it before, the default
+    #[arg(long, overrides_with = "follow_window")]
+    focus_stays: bool,
+}
+
+impl FocusArgs {
+    /// The window to focus after moving the window `moved`, given the window that was `focused`
+    /// before.
+    fn target(&self, focused: Option<u64>, moved: u64) -> Option<u64> {
+        if self.follow_window {
+            Some(moved)
+        } else {
+            focused
+        }
+    }
+}
+
+/// What `consume-into-left` or `consume-into-right` did, printed with `--json`.
+#[derive(Debug, Serialize)]
+struct ConsumeOutcome {
+    consumed_window: u64,
+    from_column: Option<usize>,
+    /// Number of times the window was consumed into the next column
+    consumes: usize,
+    /// Number of times the window was moved up within a column
+    moves_up: usize,
+    /// Number of times the window was moved down within a column
+    moves_down: usize,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Row {
+    Top,
+    Bottom,
+    /// The row the window was at in its original column
+    Keep,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 enum Direction {
+    #[value(aliases = ["l", "west"])]
+    #[serde(alias = "l", alias = "west")]
     Left,
+    #[value(aliases = ["d", "south"])]
+    #[serde(alias = "d", alias = "south")]
     Down,
+    #[value(aliases = ["u", "north"])]
+    #[serde(alias = "u", alias = "north")]
     Up,
+    #[value(aliases = ["r", "east"])]
+    #[serde(alias = "r", alias = "east")]
+    Right,
+    /// Center of the output, inside the margins
+    Center,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Which output `--snap-to` positions windows on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SnapTo {
+    /// The focused output
+    Focus,
+    /// The output containing the center of the window
+    Window,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    fn direction(&self) -> Direction {
+        match self {
+            Corner::TopLeft => Direction::TopLeft,
+            Corner::TopRight => Direction::TopRight,
+            Corner::BottomLeft => Direction::BottomLeft,
+            Corner::BottomRight => Direction::BottomRight,
+        }
+    }
+}
+
+/// Position of a tile and size of its window, as saved by `floating-maximize`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Geometry {
+    x: f64,
+    y: f64,
+    width: i32,
+    height: i32,
+}
+
+/// The last accelerated nudge, as saved by `floating-nudge --accel`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Nudge {
+    id: u64,
+    direction: Direction,
+    /// Time of the nudge in milliseconds since the Unix epoch
+    at: u64,
+    /// Number of times the distance was doubled for the last move
+    step: u32,
+}
+
+/// State file with the last accelerated nudge.
+const NUDGED: &str = "nudge.json";
+
+/// Longest time between two nudges for the second to continue the acceleration of the first.
+const NUDGE_ACCEL_TIMEOUT: Duration = Duration::from_millis(400);
+
+/// Number of times the distance of a nudge is doubled at most.
+const MAX_NUDGE_STEP: u32 = 5;
+
+/// State file with the windows stashed by `floating-stash` and their positions, last one last.
+const STASHED: &str = "stash.json";
+
+/// State file with the geometry of the maximized windows by id.
+const MAXIMIZED: &str = "maximized.json";
+
+#[derive(Debug, Clone, Copy)]
+enum Side {
+    Left,
     Right,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+fn main() -> ExitCode {
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        // Usage errors exit with 1 like other errors, as 2 means that no window is focused.
+        Err(e) if e.use_stderr() => {
+            let _ = e.print();
+            return ExitCode::FAILURE;
+        }
+        Err(e) => e.exit(),
+    };
+
+    env_logger::Builder::new()
+        .filter_level(match args.global.verbose {
+            0 => log::LevelFilter::Error,
+            1 => log::LevelFilter::Info,
+            2 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        })
+        .init();
+
+    match run(args.command, &args.global, &mut None) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let failure = e.downcast_ref::<Failure>();
+            if let Some(Failure::NoFocusedWindow) = failure {
+                log::info!("nothing to do: {e:#}");
+            } else {
+                eprintln!("Error: {e:?}");
+            }
+            ExitCode::from(failure.map_or(1, Failure::exit_code))
+        }
+    }
+}
+
+/// Run `command`, reusing the `connection` to niri if there is one.
+fn run(command: Command, global: &GlobalArgs, connection: &mut Option<Niri>) -> Result<()> {
+    let result = run_command(command, global, connection);
+    // Also record the positions from before a command that failed halfway, to undo what it did.
+    if let Some(niri) = connection {
+        niri.save_history();
+    }
+    result
+}
 
-    match args.command {
+/// Run `command` like [`run`], leaving the positions it recorded unsaved.
+fn run_command(command: Command, global: &GlobalArgs, connection: &mut Option<Niri>) -> Result<()> {
+    match command {
         Command::FloatingSnapOr {
             direction,
+            account_border,
             or_action,
-        } => {
-            let mut socket = Socket::connect()?;
-
-            let niri_ipc::Response::FocusedWindow(Some(window)) = socket
-                .send(Request::FocusedWindow)?
-                .map_err(|e| anyhow!("{e}"))?
-            else {
-                bail!("failed to receive response")
+        } => floating_snap_or(
+            connect(connection, global)?,
+            global,
+            direction,
+            account_border,
+            or_action,
+        )?,
+        Command::ToggleOverview => {
+            connect(connection, global)?.action(Action::ToggleOverview {})?
+        }
+        Command::ToggleOverviewOr { or_action } => {
+            toggle_overview_or(connect(connection, global)?, or_action)?
+        }
+        Command::FullscreenOr { or_action } => {
+            fullscreen_or(connect(connection, global)?, or_action)?
+        }
+        Command::ToggleFollowMode { native, nirius_bin } => {
+            toggle_follow_mode(connect(connection, global)?, global, native, nirius_bin)?
+        }
+        Command::ConsumeIntoLeft { consume } => {
+            consume_into(connect(connection, global)?, Side::Left, &consume)?
+        }
+        Command::ConsumeIntoRight { consume } => {
+            consume_into(connect(connection, global)?, Side::Right, &consume)?
+        }
+        Command::SwapColumnLeft { focus } => {
+            swap_column(connect(connection, global)?, Side::Left, focus)?
+        }
+        Command::SwapColumnRight { focus } => {
+            swap_column(connect(connection, global)?, Side::Right, focus)?
+        }
+        Command::ColumnWidth { preset } => {
+            column_width(connect(connection, global)?, global, preset)?
+        }
+        Command::BalanceColumns => balance_columns(connect(connection, global)?)?,
+        Command::EjectWindow { direction, focus } => {
+            eject_window(connect(connection, global)?, direction, focus)?
+        }
+        Command::ToggleFloating { direction } => {
+            toggle_floating(connect(connection, global)?, global, direction)?
+        }
+        Command::FloatingNudge {
+            direction,
+            amount,
+            repeat,
+            accel,
+        } => floating_nudge(
+            connect(connection, global)?,
+            direction,
+            amount,
+            repeat,
+            accel,
+        )?,
+        Command::FloatingResize {
+            width_pct,
+            height_pct,
+        } => floating_resize(connect(connection, global)?, width_pct, height_pct)?,
+        Command::FloatingHalf { side } => {
+            floating_half(connect(connection, global)?, global, side)?
+        }
+        Command::FloatingQuarter { corner } => {
+            floating_quarter(connect(connection, global)?, global, corner)?
+        }
+        Command::FloatingGrid { cols, rows, cell } => {
+            ensure!(
+                cols > 0 && rows > 0,
+                "the grid needs at least one column and row"
+            );
+            let Some(cells) = cols.checked_mul(rows) else {
+                bail!("the grid can't have {cols} columns and {rows} rows")
             };
+            ensure!(
+                (1..=cells).contains(&cell),
+                "the cell must be between 1 and {cells}"
+            );
 
-            if !window.is_floating {
-                socket
-                    .send(Request::Action(or_action))?
-                    .map_err(|e| anyhow!("{e}"))?;
-            } else {
-                let niri_ipc::Response::FocusedOutput(Some(output)) = socket
-                    .send(Request::FocusedOutput)?
-                    .map_err(|e| anyhow!("{e}"))?
-                else {
-                    bail!("failed to receive response")
-                };
+            floating_grid(connect(connection, global)?, global, cols, rows, cell)?
+        }
+        Command::FloatingMaximize => floating_maximize(connect(connection, global)?, global)?,
+        Command::FloatingAspect { ratio } => {
+            floating_aspect(connect(connection, global)?, global, ratio)?
+        }
+        Command::FloatingGrow { amount } => floating_grow(connect(connection, global)?, amount)?,
+        Command::SpawnFloating {
+            snap,
+            app_id,
+            open_timeout,
+            cmd,
+        } => spawn_floating(
+            connect(connection, global)?,
+            global,
+            snap,
+            app_id,
+            open_timeout,
+            cmd,
+        )?,
+        Command::FloatingSpread => floating_spread(connect(connection, global)?, global)?,
+        Command::FloatingSwap { with } => floating_swap(connect(connection, global)?, with)?,
+        Command::FloatingSnapAll { direction } => {
+            floating_snap_all(connect(connection, global)?, global, direction)?
+        }
+        Command::FloatingEqualize {
+            width,
+            height,
+            reflow,
+        } => floating_equalize(connect(connection, global)?, global, width, height, reflow)?,
+        Command::FloatingCascade { offset } => {
+            floating_cascade(connect(connection, global)?, global, offset)?
+        }
+        Command::Layout { name } => layout(connect(connection, global)?, global, name)?,
+        Command::FloatingStash => floating_stash(connect(connection, global)?, global)?,
+        Command::FloatingUnstash => floating_unstash(connect(connection, global)?)?,
+        Command::FocusedWindow { json } => focused_window(connect(connection, global)?, json)?,
+        Command::Windows {
+            app_id,
+            title,
+            json,
+        } => windows(connect(connection, global)?, app_id, title, json)?,
+        Command::FocusApp { app_id } => focus_app(connect(connection, global)?, app_id)?,
+        Command::FocusFloating { direction } => {
+            focus_floating(connect(connection, global)?, direction)?
+        }
+        Command::FloatingRaise { app_id } => floating_raise(connect(connection, global)?, app_id)?,
+        Command::FocusOrSpawn { app_id, wait, cmd } => {
+            focus_or_spawn(connect(connection, global)?, global, app_id, wait, cmd)?
+        }
+        Command::GatherApp { app_id, tile } => {
+            gather_app(connect(connection, global)?, app_id, tile)?
+        }
+        Command::CloseApp { app_id } => close_app(connect(connection, global)?, app_id)?,
+        Command::FocusTitle { pattern } => focus_title(connect(connection, global)?, pattern)?,
+        Command::Pick { focus_from_stdin } => pick(connect(connection, global)?, focus_from_stdin)?,
+        Command::Interactive { snap } => interactive(connect(connection, global)?, global, snap)?,
+        Command::FloatingPin => floating_pin(connect(connection, global)?)?,
+        Command::FloatingUnpin => floating_unpin(connect(connection, global)?)?,
+        Command::Follow => follow::run(global)?,
+        Command::Watch => watch::run(global)?,
+        Command::MoveToWorkspace { index, focus } => {
+            move_to_workspace(connect(connection, global)?, index, focus)?
+        }
+        Command::MoveToNewWorkspace { focus } => {
+            move_to_new_workspace(connect(connection, global)?, focus)?
+        }
+        Command::FloatingCycle => floating_cycle(connect(connection, global)?, global)?,
+        Command::FloatingPlace { x_pct, y_pct } => {
+            floating_place(connect(connection, global)?, global, x_pct, y_pct)?
+        }
+        Command::Debug { json } => debug(connect(connection, global)?, global, json)?,
+        Command::Outputs { json } => outputs(connect(connection, global)?, json)?,
+        Command::Workspaces { json } => workspaces(connect(connection, global)?, json)?,
+        Command::FocusMonitor { name } => focus_monitor(connect(connection, global)?, name)?,
+        Command::WorkspaceToMonitor { name } => {
+            workspace_to_monitor(connect(connection, global)?, name)?
+        }
+        Command::MoveToMonitor { name, focus } => {
+            move_to_monitor(connect(connection, global)?, name, focus)?
+        }
+        Command::CycleMonitors { wrap, focus } => {
+            cycle_monitors(connect(connection, global)?, global, wrap, focus)?
+        }
+        Command::FloatingToOutput { name } => {
+            floating_to_output(connect(connection, global)?, global, name)?
+        }
+        Command::Version => version(connect(connection, global)?)?,
+        Command::Run { keep_going } => run_script(global, connection, keep_going)?,
+        Command::Undo => undo(connect(connection, global)?)?,
+        Command::StickyCorner { corner } => sticky::run(global, &corner)?,
+        Command::Status => status::run(global)?,
+        Command::Hooks => hooks::run(global)?,
+        Command::Raw { json } => {
+            let request: Request = serde_json::from_str(&json).with_context(|| {
+                format!("{json} is not a niri request, like \"Windows\" or {{\"Action\":{{\"FocusColumnLeft\":{{}}}}}}")
+            })?;
+
+            let niri = connect(connection, global)?;
+            let response = send_raw(niri, request)?;
+            println!("{}", serde_json::to_string(&response)?);
+        }
+        Command::Quit { confirm } => {
+            let confirmed = confirm || {
+                let stdin = std::io::stdin();
+                if stdin.is_terminal() {
+                    eprint!("Quit niri and end the session? [y/N] ");
+                    let mut answer = String::new();
+                    stdin.read_line(&mut answer)?;
+                    matches!(answer.trim(), "y" | "Y" | "yes")
+                } else {
+                    false
+                }
+            };
+            ensure!(
+                confirmed,
+                "this would quit niri and end the session, pass --confirm to do it"
+            );
+
+            // Confirmed already, so niri doesn't need to ask again.
+            connect(connection, global)?.action(Action::Quit {
+                skip_confirmation: true,
+            })?;
+        }
+        Command::KbLayout { action } => kb_layout(connect(connection, global)?, action)?,
+        Command::Output { name, action } => output(connect(connection, global)?, name, action)?,
+        Command::Completions { shell } => {
+            let mut command = Args::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+        }
+    }
+
+    Ok(())
+}
 
-                const LEFT_MARGIN: f64 = 0.;
-                const BOTTOM_MARGIN: f64 = 48.;
-                const TOP_MARGIN: f64 = 0.;
-                const RIGHT_MARGIN: f64 = 0.;
-
-                let (x, y): (Option<f64>, Option<f64>) = match direction {
-                    Direction::Left => (Some(LEFT_MARGIN), None),
-                    Direction::Down => (
-                        None,
-                        Some(
-                            output.logical.map(|l| l.height as f64).unwrap_or_default()
-                                - BOTTOM_MARGIN
-                                - window.layout.tile_size.1,
-                        ),
-                    ),
-                    Direction::Up => (None, Some(TOP_MARGIN)),
-                    Direction::Right => (
-                        Some(
-                            output.logical.map(|l| l.width as f64).unwrap_or_default()
-                                - RIGHT_MARGIN
-                                - window.layout.tile_size.0,
-                        ),
-                        None,
-                    ),
+/// The `floating-snap-or` command.
+fn floating_snap_or(
+    niri: &mut Niri<impl Ipc>,
+    global: &GlobalArgs,
+    direction: Vec<Direction>,
+    account_border: bool,
+    or_action: Option<Action>,
+) -> Result<()> {
+    let window = niri.window()?;
+
+    if !window.is_floating {
+        let or_action = match or_action {
+            Some(action) => Some(action),
+            None => {
+                let config = Config::for_args(global)?;
+                let action = match direction.as_slice() {
+                    [direction] => config.or_action(direction),
+                    _ => None,
                 };
+                action.cloned()
+            }
+        };
+        if let Some(or_action) = or_action {
+            niri.action(or_action)?;
+        }
+    } else {
+        let mut config = Config::for_args(global)?;
+        config.account_border |= account_border;
+        snap_floating(niri, &window, &direction, &config)?;
+    }
 
-                socket
-                    .send(Request::Action(Action::MoveFloatingWindow {
-                        id: Some(window.id),
-                        x: x.map(|x| output.logical.map(|l| l.x as f64).unwrap_or_default() + x)
-                            .map(PositionChange::SetFixed)
-                            .unwrap_or(PositionChange::AdjustFixed(0.)),
-                        y: y.map(|y| output.logical.map(|l| l.y as f64).unwrap_or_default() + y)
-                            .map(PositionChange::SetFixed)
-                            .unwrap_or(PositionChange::AdjustFixed(0.)),
-                    }))?
-                    .map_err(|e| anyhow!("{e}"))?;
-            }
-        }
-        Command::ToggleFollowMode => {
-            let mut socket = Socket::connect()?;
-
-            let niri_ipc::Response::FocusedWindow(Some(window)) = socket
-                .send(Request::FocusedWindow)?
-                .map_err(|e| anyhow!("{e}"))?
-            else {
-                bail!("failed to receive response")
-            };
+    Ok(())
+}
+
+/// The `toggle-overview-or` command.
+fn toggle_overview_or(niri: &mut Niri<impl Ipc>, or_action: Action) -> Result<()> {
+    let is_open = match niri.request(Request::OverviewState)? {
+        Response::OverviewState(overview) => overview.is_open,
+        response => bail!("unexpected response to OverviewState: {response:?}"),
+    };
+
+    let windows = niri.windows()?;
+    let used = niri
+        .workspaces()?
+        .iter()
+        .filter(|ws| windows.iter().any(|w| w.workspace_id == Some(ws.id)))
+        .count();
+
+    if is_open || used >= 2 {
+        niri.action(Action::ToggleOverview {})?;
+    } else {
+        niri.action(or_action)?;
+    }
+
+    Ok(())
+}
+
+/// The `fullscreen-or` command.
+fn fullscreen_or(niri: &mut Niri<impl Ipc>, or_action: Action) -> Result<()> {
+    let window = niri.window()?;
+
+    if window.is_floating {
+        niri.action(or_action)?;
+    } else {
+        niri.action(Action::FullscreenWindow {
+            id: Some(window.id),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// The `toggle-follow-mode` command.
+fn toggle_follow_mode(
+    niri: &mut Niri<impl Ipc>,
+    global: &GlobalArgs,
+    native: bool,
+    nirius_bin: Option<PathBuf>,
+) -> Result<()> {
+    let window = niri.window()?;
 
-            if window.is_floating {
-                std::process::Command::new("nirius")
-                    .stdout(Stdio::inherit())
-                    .stdin(Stdio::inherit())
-                    .arg("toggle-follow-mode")
-                    .output()?;
+    if window.is_floating {
+        let nirius = if native {
+            None
+        } else {
+            let config = Config::for_args(global)?;
+            match nirius_bin.or(config.nirius_path) {
+                // A nirius that was asked for has to be there.
+                Some(path) => Some(
+                    executable(&path)
+                        .ok_or_else(|| anyhow!("there is no nirius at {}", path.display()))?,
+                ),
+                None => executable(Path::new("nirius")),
             }
+        };
+        let Some(nirius) = nirius else {
+            follow::toggle(window.id, niri.dry_run())?;
+            return Ok(());
+        };
+
+        // nirius acts on the focused window.
+        if !window.is_focused {
+            niri.action(Action::FocusWindow { id: window.id })?;
+        }
+
+        if niri.dry_run() {
+            eprintln!("{} toggle-follow-mode", nirius.display());
+        } else {
+            let output = std::process::Command::new(&nirius)
+                .stdout(Stdio::inherit())
+                .stdin(Stdio::inherit())
+                .arg("toggle-follow-mode")
+                .output()
+                .with_context(|| format!("failed to run {}", nirius.display()))?;
+
+            ensure!(
+                output.status.success(),
+                "nirius failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
         }
-        Command::ConsumeIntoLeft => {
-            let mut socket = Socket::connect()?;
+    }
+
+    Ok(())
+}
+
+/// The `column-width` command.
+fn column_width(
+    niri: &mut Niri<impl Ipc>,
+    global: &GlobalArgs,
+    preset: Option<usize>,
+) -> Result<()> {
+    let window = niri.tiled_window()?;
+    let config = Config::for_args(global)?;
+
+    let widths = config.column_widths();
+    ensure!(!widths.is_empty(), "column_widths in the config is empty");
 
-            let niri_ipc::Response::FocusedWindow(Some(window)) = socket
-                .send(Request::FocusedWindow)?
-                .map_err(|e| anyhow!("{e}"))?
-            else {
-                bail!("failed to receive response")
+    let width = match preset {
+        Some(preset) => *widths
+            .get(preset.wrapping_sub(1))
+            .ok_or_else(|| anyhow!("the preset must be between 1 and {}", widths.len()))?,
+        None => {
+            let Some(logical) = niri.current_output(&window)?.logical else {
+                bail!("the output has no logical geometry")
             };
+            let current = window.layout.tile_size.0 / logical.width as f64 * 100.;
 
-            ensure!(!window.is_floating, "cannot consume a floating window");
+            let closest = (0..widths.len())
+                .min_by(|&a, &b| {
+                    (widths[a] - current)
+                        .abs()
+                        .total_cmp(&(widths[b] - current).abs())
+                })
+                .unwrap_or(0);
+            widths[(closest + 1) % widths.len()]
+        }
+    };
 
-            if let Some((in_ws, in_col)) = window.layout.pos_in_scrolling_layout {
-                ensure!(
-                    in_ws != 1,
-                    "cannot consume a window in the first column into left"
-                );
+    // Setting the column width acts on the focused column.
+    if !window.is_focused {
+        niri.action(Action::FocusWindow { id: window.id })?;
+    }
+    niri.action(Action::SetColumnWidth {
+        change: SizeChange::SetProportion(width),
+    })?;
 
-                if in_col != 1 {
-                    for _ in 0..(in_col - 1) {
-                        let _ = socket
-                            .send(Request::Action(Action::MoveWindowUp {}))?
-                            .map_err(|e| anyhow!("{e}"))?;
-                    }
-                }
-            }
+    Ok(())
+}
 
-            let _ = socket
-                .send(Request::Action(Action::FocusColumnLeft {}))?
-                .map_err(|e| anyhow!("{e}"))?;
-            let _ = socket
-                .send(Request::Action(Action::ConsumeWindowIntoColumn {}))?
-                .map_err(|e| anyhow!("{e}"))?;
+/// The `balance-columns` command.
+fn balance_columns(niri: &mut Niri<impl Ipc>) -> Result<()> {
+    let window = niri.window()?;
 
-            let _ = socket
-                .send(Request::Action(Action::FocusWindow { id: window.id }))?
-                .map_err(|e| anyhow!("{e}"))?;
+    // The topmost window of each column, to focus the column with.
+    let mut columns: HashMap<usize, (usize, u64)> = HashMap::new();
+    for w in niri.windows()? {
+        if w.workspace_id == window.workspace_id
+            && let Some((column, row)) = w.layout.pos_in_scrolling_layout
+        {
+            let entry = columns.entry(column).or_insert((row, w.id));
+            if row < entry.0 {
+                *entry = (row, w.id);
+            }
         }
     }
+    ensure!(!columns.is_empty(), "the workspace has no columns");
+
+    let mut columns: Vec<_> = columns.into_iter().collect();
+    columns.sort_by_key(|&(column, _)| column);
+    let width = 100. / columns.len() as f64;
+
+    // Setting the column width acts on the focused column.
+    for (_, (_, id)) in &columns {
+        niri.action(Action::FocusWindow { id: *id })?;
+        niri.action(Action::SetColumnWidth {
+            change: SizeChange::SetProportion(width),
+        })?;
+    }
+
+    niri.action(Action::FocusWindow { id: window.id })?;
+
+    Ok(())
+}
+
+/// The `eject-window` command.
+fn eject_window(niri: &mut Niri<impl Ipc>, direction: Direction, focus: FocusArgs) -> Result<()> {
+    let window = niri.tiled_window()?;
+    let focused = if window.is_focused {
+        Some(window.id)
+    } else {
+        focused_window_id(niri)?
+    };
+
+    let Some((column, _)) = window.layout.pos_in_scrolling_layout else {
+        bail!("the window is not in a column")
+    };
+    let len = niri
+        .windows()?
+        .iter()
+        .filter(|w| w.workspace_id == window.workspace_id)
+        .filter_map(|w| w.layout.pos_in_scrolling_layout)
+        .filter(|&(c, _)| c == column)
+        .count();
+    // On a window alone in its column these actions would consume it instead.
+    ensure!(len > 1, "the window is already alone in its column");
+
+    let id = Some(window.id);
+    niri.action(match direction {
+        Direction::Left => Action::ConsumeOrExpelWindowLeft { id },
+        Direction::Right => Action::ConsumeOrExpelWindowRight { id },
+        _ => bail!("a window can only be ejected left or right"),
+    })?;
+    if let Some(id) = focus.target(focused, window.id) {
+        niri.action(Action::FocusWindow { id })?;
+    }
 
     Ok(())
 }
+
+/// The `toggle-floating` command.
+fn toggle_floating(
+    niri: &mut Niri<impl Ipc>,
+    global: &GlobalArgs,
+    direction: Option<Direction>,
+) -> Result<()> {
+    let window = niri.window()?;
+
+    niri.action(Action::ToggleWindowFloating {
+        id: Some(window.id),
+    })?;
+
+    // A window that was floating is tiled now, so there is nothing to snap.
+    if let Some(direction) = direction
+        && !window.is_floating
+    {
+        // The tile size changes when the window leaves the scrolling layout.
+        let window = niri.window()?;
+
+        if window.is_floating || niri.dry_run() {
+            let config = Config::for_args(global)?;
+            snap_floating(niri, &window, &[direction], &config)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The `floating-nudge` command.
+fn floating_nudge(
+    niri: &mut Niri<impl Ipc>,
+    direction: Direction,
+    amount: f64,
+    repeat: u32,
+    accel: bool,
+) -> Result<()> {
+    let window = niri.floating_window()?;
+
+    let (x, y) = match direction {
+        Direction::Left => (-amount, 0.),
+        Direction::Down => (0., amount),
+        Direction::Up => (0., -amount),
+        Direction::Right => (amount, 0.),
+        Direction::Center => bail!("cannot nudge a window towards the center"),
+        Direction::TopLeft => (-amount, -amount),
+        Direction::TopRight => (amount, -amount),
+        Direction::BottomLeft => (-amount, amount),
+        Direction::BottomRight => (amount, amount),
+    };
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let mut step = 0;
+    if accel {
+        let last: Option<Nudge> = state::load(NUDGED)?;
+        if let Some(last) = last
+            && last.id == window.id
+            && last.direction == direction
+            && now.saturating_sub(last.at) < NUDGE_ACCEL_TIMEOUT.as_millis() as u64
+        {
+            step = last.step + 1;
+        }
+    }
+
+    for _ in 0..repeat {
+        let factor = if accel {
+            2f64.powi(step.min(MAX_NUDGE_STEP) as i32)
+        } else {
+            1.
+        };
+        niri.action(Action::MoveFloatingWindow {
+            id: Some(window.id),
+            x: PositionChange::AdjustFixed(x * factor),
+            y: PositionChange::AdjustFixed(y * factor),
+        })?;
+        step += 1;
+    }
+
+    if accel && !niri.dry_run() {
+        let nudge = Nudge {
+            id: window.id,
+            direction,
+            at: now,
+            step: step.saturating_sub(1),
+        };
+        state::save(NUDGED, &Some(nudge))?;
+    }
+
+    Ok(())
+}
+
+/// The `floating-resize` command.
+fn floating_resize(
+    niri: &mut Niri<impl Ipc>,
+    width_pct: Option<f64>,
+    height_pct: Option<f64>,
+) -> Result<()> {
+    let window = niri.floating_window()?;
+
+    let output = niri.target_output(&window)?;
+    let Some(logical) = output.logical else {
+        bail!("the output has no logical geometry")
+    };
+
+    if let Some(pct) = width_pct {
+        niri.action(Action::SetWindowWidth {
+            id: Some(window.id),
+            change: percent_size(pct, logical.width),
+        })?;
+    }
+    if let Some(pct) = height_pct {
+        niri.action(Action::SetWindowHeight {
+            id: Some(window.id),
+            change: percent_size(pct, logical.height),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// The `floating-half` command.
+fn floating_half(niri: &mut Niri<impl Ipc>, global: &GlobalArgs, side: Direction) -> Result<()> {
+    let window = niri.floating_window()?;
+
+    let output = niri.target_output(&window)?;
+    let config = Config::for_args(global)?;
+    let WorkArea {
+        x,
+        y,
+        width,
+        height,
+        ..
+    } = WorkArea::new(&output, &config)?;
+
+    let (x, y, width, height) = match side {
+        Direction::Left => (x, y, width / 2., height),
+        Direction::Down => (x, y + height / 2., width, height / 2.),
+        Direction::Up => (x, y, width, height / 2.),
+        Direction::Right => (x + width / 2., y, width / 2., height),
+        _ => bail!("a half can only be left, down, up or right"),
+    };
+
+    fit_floating(niri, &window, x, y, width, height)?;
+
+    Ok(())
+}
+
+/// The `floating-quarter` command.
+fn floating_quarter(niri: &mut Niri<impl Ipc>, global: &GlobalArgs, corner: Corner) -> Result<()> {
+    let window = niri.floating_window()?;
+
+    let output = niri.target_output(&window)?;
+    let config = Config::for_args(global)?;
+    let WorkArea {
+        x,
+        y,
+        width,
+        height,
+        ..
+    } = WorkArea::new(&output, &config)?;
+
+    let (width, height) = (width / 2., height / 2.);
+    let (x, y) = match corner {
+        Corner::TopLeft => (x, y),
+        Corner::TopRight => (x + width, y),
+        Corner::BottomLeft => (x, y + height),
+        Corner::BottomRight => (x + width, y + height),
+    };
+
+    fit_floating(niri, &window, x, y, width, height)?;
+
+    Ok(())
+}
+
+/// The `floating-grid` command, with `cell` checked to be in the grid already.
+fn floating_grid(
+    niri: &mut Niri<impl Ipc>,
+    global: &GlobalArgs,
+    cols: u32,
+    rows: u32,
+    cell: u32,
+) -> Result<()> {
+    let window = niri.floating_window()?;
+
+    let output = niri.target_output(&window)?;
+    let config = Config::for_args(global)?;
+    let WorkArea {
+        x,
+        y,
+        width,
+        height,
+        ..
+    } = WorkArea::new(&output, &config)?;
+
+    let (width, height) = (width / cols as f64, height / rows as f64);
+    let (col, row) = ((cell - 1) % cols, (cell - 1) / cols);
+
+    fit_floating(
+        niri,
+        &window,
+        x + col as f64 * width,
+        y + row as f64 * height,
+        width,
+        height,
+    )?;
+
+    Ok(())
+}
+
+/// The `floating-maximize` command.
+fn floating_maximize(niri: &mut Niri<impl Ipc>, global: &GlobalArgs) -> Result<()> {
+    let window = niri.floating_window()?;
+    // The window is maximized where it is, rather than moved to another output.
+    let output = niri.current_output(&window)?;
+
+    let mut maximized: HashMap<u64, Geometry> = state::load(MAXIMIZED)?;
+    // Window ids are never reused, so entries of closed windows are only clutter.
+    let windows = niri.windows()?;
+    maximized.retain(|id, _| windows.iter().any(|w| w.id == *id));
+
+    if let Some(geometry) = maximized.remove(&window.id) {
+        niri.action(Action::SetWindowWidth {
+            id: Some(window.id),
+            change: SizeChange::SetFixed(geometry.width),
+        })?;
+        niri.action(Action::SetWindowHeight {
+            id: Some(window.id),
+            change: SizeChange::SetFixed(geometry.height),
+        })?;
+        niri.action(Action::MoveFloatingWindow {
+            id: Some(window.id),
+            x: PositionChange::SetFixed(geometry.x),
+            y: PositionChange::SetFixed(geometry.y),
+        })?;
+    } else {
+        let Some((x, y)) = absolute_position(&window, &output) else {
+            bail!("the window has no position")
+        };
+        let (width, height) = window.layout.window_size;
+        maximized.insert(
+            window.id,
+            Geometry {
+                x,
+                y,
+                width,
+                height,
+            },
+        );
+
+        let config = Config::for_args(global)?;
+        let WorkArea {
+            x,
+            y,
+            width,
+            height,
+            ..
+        } = WorkArea::new(&output, &config)?;
+        fit_floating(niri, &window, x, y, width, height)?;
+    }
+
+    if !niri.dry_run() {
+        state::save(MAXIMIZED, &maximized)?;
+    }
+
+    Ok(())
+}
+
+/// The `floating-aspect` command.
+fn floating_aspect(niri: &mut Niri<impl Ipc>, global: &GlobalArgs, ratio: f64) -> Result<()> {
+    ensure!(
+        ratio.is_finite() && ratio > 0.,
+        "the ratio must be a positive number"
+    );
+
+    let window = niri.floating_window()?;
+    let output = niri.target_output(&window)?;
+    let config = Config::for_args(global)?;
+    let area = WorkArea::new(&output, &config)?;
+
+    // Whichever of the width and the height of the area runs out first limits the size.
+    let width = area.width.min(area.height * ratio);
+    let height = width / ratio;
+    let (x, y) = area.center((width, height));
+
+    fit_floating(
+        niri,
+        &window,
+        area.round_x(x),
+        area.round_y(y),
+        width,
+        height,
+    )?;
+
+    Ok(())
+}
+
+/// The `floating-grow` command.
+fn floating_grow(niri: &mut Niri<impl Ipc>, amount: f64) -> Result<()> {
+    let window = niri.floating_window()?;
+    let output = niri.current_output(&window)?;
+    let Some((x, y)) = absolute_position(&window, &output) else {
+        bail!("the window has no position")
+    };
+
+    let (width, height) = window.layout.tile_size;
+    // Shrinking stops at a tile of a single pixel.
+    let new_width = (width + amount).max(1.);
+    let new_height = (height + amount).max(1.);
+
+    fit_floating(
+        niri,
+        &window,
+        x - (new_width - width) / 2.,
+        y - (new_height - height) / 2.,
+        new_width,
+        new_height,
+    )?;
+
+    Ok(())
+}
+
+/// The `spawn-floating` command.
+fn spawn_floating(
+    niri: &mut Niri<impl Ipc>,
+    global: &GlobalArgs,
+    snap: Direction,
+    app_id: Option<String>,
+    open_timeout: u64,
+    cmd: Vec<String>,
+) -> Result<()> {
+    let config = Config::for_args(global)?;
+
+    if niri.dry_run() {
+        eprintln!("spawn {cmd:?}");
+        return Ok(());
+    }
+
+    let spawn = || {
+        let child = std::process::Command::new(&cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("failed to run {}", cmd[0]))?;
+        Ok(child.id() as i32)
+    };
+    let matches = |window: &Window, &pid: &i32| {
+        window.pid == Some(pid) || app_id.is_some() && window.app_id == app_id
+    };
+    let Some(id) = wait_for_new_window(global, Duration::from_secs(open_timeout), spawn, matches)?
+    else {
+        bail!(
+            "no window of {} opened within {open_timeout} seconds",
+            cmd[0]
+        )
+    };
+
+    let window = niri
+        .windows()?
+        .into_iter()
+        .find(|w| w.id == id)
+        .ok_or_else(|| anyhow!("the window {id} disappeared"))?;
+
+    let window = if window.is_floating {
+        window
+    } else {
+        niri.action(Action::ToggleWindowFloating { id: Some(id) })?;
+        // The tile size changes when the window leaves the scrolling layout.
+        niri.windows()?
+            .into_iter()
+            .find(|w| w.id == id)
+            .ok_or_else(|| anyhow!("the window {id} disappeared"))?
+    };
+
+    snap_floating(niri, &window, &[snap], &config)?;
+
+    Ok(())
+}
+
+/// The `floating-spread` command.
+fn floating_spread(niri: &mut Niri<impl Ipc>, global: &GlobalArgs) -> Result<()> {
+    let output = niri.output()?;
+    let config = Config::for_args(global)?;
+    let area = WorkArea::new(&output, &config)?;
+
+    let windows = floating_windows_on(niri, &output)?;
+    spread(niri, &windows, &area)?;
+
+    Ok(())
+}
+
+/// The `floating-swap` command.
+fn floating_swap(niri: &mut Niri<impl Ipc>, with: u64) -> Result<()> {
+    let window = niri.floating_window()?;
+    ensure!(window.id != with, "the window can't be swapped with itself");
+    let other = niri
+        .windows()?
+        .into_iter()
+        .find(|w| w.id == with)
+        .ok_or_else(|| anyhow!("there is no window with id {with}"))?;
+    ensure!(other.is_floating, "the window {with} is not floating");
+
+    let mut positions = Vec::new();
+    for w in [&window, &other] {
+        let output = niri.current_output(w)?;
+        let position = absolute_position(w, &output)
+            .ok_or_else(|| anyhow!("the window {} has no position", w.id))?;
+        positions.push(position);
+    }
+
+    for (w, (x, y)) in [&window, &other]
+        .into_iter()
+        .zip(positions.into_iter().rev())
+    {
+        niri.action(Action::MoveFloatingWindow {
+            id: Some(w.id),
+            x: PositionChange::SetFixed(x),
+            y: PositionChange::SetFixed(y),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// The `floating-snap-all` command.
+fn floating_snap_all(
+    niri: &mut Niri<impl Ipc>,
+    global: &GlobalArgs,
+    direction: Direction,
+) -> Result<()> {
+    let output = niri.output()?;
+    let config = Config::for_args(global)?;
+
+    let windows = floating_windows_on(niri, &output)?;
+    ensure!(
+        !windows.is_empty(),
+        "there are no floating windows on the output"
+    );
+    for window in &windows {
+        snap_floating_on(
+            niri,
+            window,
+            std::slice::from_ref(&direction),
+            &output,
+            &config,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The `floating-equalize` command.
+fn floating_equalize(
+    niri: &mut Niri<impl Ipc>,
+    global: &GlobalArgs,
+    width: f64,
+    height: f64,
+    reflow: bool,
+) -> Result<()> {
+    let output = niri.output()?;
+    let mut windows = floating_windows_on(niri, &output)?;
+
+    for window in &mut windows {
+        resize_tile(niri, window, width, height)?;
+        // Reflowing uses the new size.
+        window.layout.tile_size = (width, height);
+    }
+
+    if reflow {
+        let config = Config::for_args(global)?;
+        spread(niri, &windows, &WorkArea::new(&output, &config)?)?;
+    }
+
+    Ok(())
+}
+
+/// The `floating-cascade` command.
+fn floating_cascade(niri: &mut Niri<impl Ipc>, global: &GlobalArgs, offset: f64) -> Result<()> {
+    let output = niri.output()?;
+    let config = Config::for_args(global)?;
+    let WorkArea { x, y, .. } = WorkArea::new(&output, &config)?;
+
+    for (i, window) in floating_windows_on(niri, &output)?.into_iter().enumerate() {
+        let distance = i as f64 * offset;
+        niri.action(Action::MoveFloatingWindow {
+            id: Some(window.id),
+            x: PositionChange::SetFixed(x + distance),
+            y: PositionChange::SetFixed(y + distance),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// The `layout` command.
+fn layout(niri: &mut Niri<impl Ipc>, global: &GlobalArgs, name: String) -> Result<()> {
+    let config = Config::for_args(global)?;
+
+    let Some(layout) = config.layout(&name) else {
+        bail!("there is no layout named {name} in the config")
+    };
+
+    let matching = |windows: &[Window], app_id: &str| -> Vec<Window> {
+        windows
+            .iter()
+            .filter(|w| w.is_floating && w.app_id.as_deref() == Some(app_id))
+            .cloned()
+            .collect()
+    };
+
+    let windows = niri.windows()?;
+    for entry in &layout.windows {
+        for window in matching(&windows, &entry.app_id) {
+            let output = niri.current_output(&window)?;
+            let Some(logical) = output.logical else {
+                bail!("the output has no logical geometry")
+            };
+
+            if let Some(pct) = entry.width_pct {
+                niri.action(Action::SetWindowWidth {
+                    id: Some(window.id),
+                    change: percent_size(pct, logical.width),
+                })?;
+            }
+            if let Some(pct) = entry.height_pct {
+                niri.action(Action::SetWindowHeight {
+                    id: Some(window.id),
+                    change: percent_size(pct, logical.height),
+                })?;
+            }
+        }
+    }
+
+    // Snap with the tile sizes after resizing.
+    let windows = niri.windows()?;
+    for entry in &layout.windows {
+        let Some(direction) = &entry.snap else {
+            continue;
+        };
+        for window in matching(&windows, &entry.app_id) {
+            let output = niri.current_output(&window)?;
+            snap_floating_on(
+                niri,
+                &window,
+                std::slice::from_ref(direction),
+                &output,
+                &config,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The `floating-stash` command.
+fn floating_stash(niri: &mut Niri<impl Ipc>, global: &GlobalArgs) -> Result<()> {
+    let window = niri.floating_window()?;
+    let output = niri.current_output(&window)?;
+    let config = Config::for_args(global)?;
+
+    let name = config.stash_workspace();
+    let Some(stash) = niri
+        .workspaces()?
+        .into_iter()
+        .find(|ws| ws.name.as_deref() == Some(name))
+    else {
+        bail!("there is no workspace named {name}, it has to be declared in the niri config")
+    };
+    ensure!(
+        window.workspace_id != Some(stash.id),
+        "the window is stashed already"
+    );
+
+    let Some((x, y)) = absolute_position(&window, &output) else {
+        bail!("the window has no position")
+    };
+
+    niri.action(Action::MoveWindowToWorkspace {
+        window_id: Some(window.id),
+        reference: WorkspaceReferenceArg::Id(stash.id),
+        focus: false,
+    })?;
+
+    if !niri.dry_run() {
+        let mut stashed: Vec<history::Entry> = state::load(STASHED)?;
+        stashed.push(history::Entry {
+            id: window.id,
+            x,
+            y,
+        });
+        state::save(STASHED, &stashed)?;
+    }
+
+    Ok(())
+}
+
+/// The `floating-unstash` command.
+fn floating_unstash(niri: &mut Niri<impl Ipc>) -> Result<()> {
+    let Some(workspace) = niri.workspaces()?.into_iter().find(|ws| ws.is_focused) else {
+        bail!("no workspace is focused")
+    };
+    let windows = niri.windows()?;
+    let mut stashed: Vec<history::Entry> = state::load(STASHED)?;
+
+    // Stashed windows that were closed since are skipped.
+    let (entry, window) = loop {
+        let Some(entry) = stashed.pop() else {
+            bail!("there is no stashed window")
+        };
+        if let Some(window) = windows.iter().find(|w| w.id == entry.id) {
+            break (entry, window);
+        }
+    };
+
+    niri.action(Action::MoveWindowToWorkspace {
+        window_id: Some(window.id),
+        reference: WorkspaceReferenceArg::Id(workspace.id),
+        focus: false,
+    })?;
+    if window.is_floating {
+        niri.action(Action::MoveFloatingWindow {
+            id: Some(window.id),
+            x: PositionChange::SetFixed(entry.x),
+            y: PositionChange::SetFixed(entry.y),
+        })?;
+    }
+    niri.action(Action::FocusWindow { id: window.id })?;
+
+    if !niri.dry_run() {
+        state::save(STASHED, &stashed)?;
+    }
+
+    Ok(())
+}
+
+/// The `focused-window` command.
+fn focused_window(niri: &mut Niri<impl Ipc>, json: bool) -> Result<()> {
+    let window = niri.focused_window()?;
+
+    if json {
+        println!("{}", serde_json::to_string(&window)?);
+    } else {
+        println!("id: {}", window.id);
+        println!("app_id: {}", window.app_id.as_deref().unwrap_or_default());
+        println!("title: {}", window.title.as_deref().unwrap_or_default());
+        println!("is_floating: {}", window.is_floating);
+        println!(
+            "tile_size: {}x{}",
+            window.layout.tile_size.0, window.layout.tile_size.1
+        );
+    }
+
+    Ok(())
+}
+
+/// The `windows` command.
+fn windows(
+    niri: &mut Niri<impl Ipc>,
+    app_id: Option<String>,
+    title: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let mut windows = niri.windows()?;
+    windows.retain(|w| {
+        app_id
+            .as_ref()
+            .is_none_or(|app_id| w.app_id.as_ref() == Some(app_id))
+            && title
+                .as_ref()
+                .is_none_or(|title| w.title.as_ref().is_some_and(|t| t.contains(title.as_str())))
+    });
+    windows.sort_by_key(|w| w.id);
+
+    if json {
+        println!("{}", serde_json::to_string(&windows)?);
+    } else {
+        println!(
+            "{:<8} {:<24} {:<10} {:<9} TITLE",
+            "ID", "APP_ID", "WORKSPACE", "FLOATING"
+        );
+        for w in windows {
+            println!(
+                "{:<8} {:<24} {:<10} {:<9} {}",
+                w.id,
+                w.app_id.as_deref().unwrap_or_default(),
+                w.workspace_id.map(|id| id.to_string()).unwrap_or_default(),
+                w.is_floating,
+                w.title.as_deref().unwrap_or_default(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The `focus-app` command.
+fn focus_app(niri: &mut Niri<impl Ipc>, app_id: String) -> Result<()> {
+    let found = focus_first(niri, |w| w.app_id.as_ref() == Some(&app_id))?;
+    ensure!(found, "there is no window with app id {app_id}");
+
+    Ok(())
+}
+
+/// The `focus-floating` command.
+fn focus_floating(niri: &mut Niri<impl Ipc>, direction: Direction) -> Result<()> {
+    let window = niri.window()?;
+    let Some((from_x, from_y)) = tile_center(&window) else {
+        bail!("the window has no position")
+    };
+    let output = niri.output()?;
+
+    let (dx, dy): (f64, f64) = match direction {
+        Direction::Left => (-1., 0.),
+        Direction::Down => (0., 1.),
+        Direction::Up => (0., -1.),
+        Direction::Right => (1., 0.),
+        Direction::TopLeft => (-1., -1.),
+        Direction::TopRight => (1., -1.),
+        Direction::BottomLeft => (-1., 1.),
+        Direction::BottomRight => (1., 1.),
+        Direction::Center => bail!("there is no window in the direction of the center"),
+    };
+
+    // A candidate has to be on the side of each axis the direction points to.
+    let nearest = floating_windows_on(niri, &output)?
+        .into_iter()
+        .filter(|w| w.id != window.id)
+        .filter_map(|w| {
+            let (x, y) = tile_center(&w)?;
+            let (off_x, off_y) = (x - from_x, y - from_y);
+            let on_side = |off: f64, d: f64| d == 0. || off * d > 0.;
+            (on_side(off_x, dx) && on_side(off_y, dy)).then(|| (w.id, off_x.hypot(off_y)))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+
+    let Some((id, _)) = nearest else {
+        bail!("there is no floating window in the direction {direction:?}")
+    };
+    niri.action(Action::FocusWindow { id })?;
+
+    Ok(())
+}
+
+/// The `floating-raise` command.
+fn floating_raise(niri: &mut Niri<impl Ipc>, app_id: String) -> Result<()> {
+    let mut windows = niri.windows()?;
+    windows.retain(|w| w.is_floating && w.app_id.as_ref() == Some(&app_id));
+    windows.sort_by_key(|w| w.id);
+    ensure!(
+        !windows.is_empty(),
+        "there is no floating window with app id {app_id}"
+    );
+
+    for window in &windows {
+        niri.action(Action::FocusWindow { id: window.id })?;
+    }
+    if !niri.dry_run() {
+        match windows.len() {
+            1 => eprintln!("raised 1 window"),
+            n => eprintln!("raised {n} windows"),
+        }
+    }
+
+    Ok(())
+}
+
+/// The `focus-or-spawn` command.
+fn focus_or_spawn(
+    niri: &mut Niri<impl Ipc>,
+    global: &GlobalArgs,
+    app_id: String,
+    wait: Option<u64>,
+    cmd: Vec<String>,
+) -> Result<()> {
+    if focus_first(niri, |w| w.app_id.as_ref() == Some(&app_id))? {
+        if !niri.dry_run() {
+            eprintln!("focused a window of {app_id}");
+        }
+        return Ok(());
+    }
+
+    // Nothing opens in dry-run mode, so there is nothing to wait for either.
+    let Some(wait) = wait.filter(|_| !niri.dry_run()) else {
+        niri.action(Action::Spawn { command: cmd })?;
+        if !niri.dry_run() {
+            eprintln!("spawned {app_id}");
+        }
+        return Ok(());
+    };
+
+    let spawn = || {
+        niri.action(Action::Spawn { command: cmd })?;
+        eprintln!("spawned {app_id}");
+        Ok(())
+    };
+    let matches = |window: &Window, _: &()| window.app_id.as_ref() == Some(&app_id);
+    let Some(id) = wait_for_new_window(global, Duration::from_secs(wait), spawn, matches)? else {
+        bail!("no window of {app_id} opened within {wait} seconds")
+    };
+
+    niri.action(Action::FocusWindow { id })?;
+
+    Ok(())
+}
+
+/// The `gather-app` command.
+fn gather_app(niri: &mut Niri<impl Ipc>, app_id: String, tile: bool) -> Result<()> {
+    let Some(workspace) = niri.workspaces()?.into_iter().find(|ws| ws.is_focused) else {
+        bail!("no workspace is focused")
+    };
+
+    let mut windows = niri.windows()?;
+    windows.retain(|w| w.app_id.as_ref() == Some(&app_id) && w.workspace_id != Some(workspace.id));
+    windows.sort_by_key(|w| w.id);
+
+    for w in windows {
+        niri.action(Action::MoveWindowToWorkspace {
+            window_id: Some(w.id),
+            reference: WorkspaceReferenceArg::Id(workspace.id),
+            focus: false,
+        })?;
+        if tile && w.is_floating {
+            niri.action(Action::MoveWindowToTiling { id: Some(w.id) })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The `close-app` command.
+fn close_app(niri: &mut Niri<impl Ipc>, app_id: String) -> Result<()> {
+    let mut windows = niri.windows()?;
+    windows.retain(|w| w.app_id.as_ref() == Some(&app_id));
+    windows.sort_by_key(|w| w.id);
+    ensure!(
+        !windows.is_empty(),
+        "there is no window with app id {app_id}"
+    );
+
+    for w in &windows {
+        niri.action(Action::CloseWindow { id: Some(w.id) })?;
+    }
+
+    if !niri.dry_run() {
+        eprintln!("closed {} windows", windows.len());
+    }
+
+    Ok(())
+}
+
+/// The `focus-title` command.
+fn focus_title(niri: &mut Niri<impl Ipc>, pattern: String) -> Result<()> {
+    let found = focus_first(niri, |w| {
+        w.title
+            .as_ref()
+            .is_some_and(|t| t.contains(pattern.as_str()))
+    })?;
+    ensure!(
+        found,
+        "there is no window with a title containing {pattern:?}"
+    );
+
+    Ok(())
+}
+
+/// The `pick` command.
+fn pick(niri: &mut Niri<impl Ipc>, focus_from_stdin: bool) -> Result<()> {
+    if focus_from_stdin {
+        let input = std::io::read_to_string(std::io::stdin())?;
+        if let Some(id) = picked_id(&input)? {
+            niri.action(Action::FocusWindow { id })?;
+        }
+    } else {
+        let mut windows = niri.windows()?;
+        windows.sort_by_key(|w| w.id);
+
+        for w in windows {
+            println!("{}", pick_line(&w));
+        }
+    }
+
+    Ok(())
+}
+
+/// The `interactive` command.
+fn interactive(niri: &mut Niri<impl Ipc>, global: &GlobalArgs, snap: Direction) -> Result<()> {
+    let config = Config::for_args(global)?;
+    interactive::run(niri, &snap, &config)?;
+
+    Ok(())
+}
+
+/// The `floating-pin` command.
+fn floating_pin(niri: &mut Niri<impl Ipc>) -> Result<()> {
+    let window = niri.floating_window()?;
+    if !follow::set(window.id, true, niri.dry_run())? {
+        eprintln!("the window is already pinned");
+    }
+
+    Ok(())
+}
+
+/// The `floating-unpin` command.
+fn floating_unpin(niri: &mut Niri<impl Ipc>) -> Result<()> {
+    let window = niri.floating_window()?;
+    ensure!(
+        follow::set(window.id, false, niri.dry_run())?,
+        "the window is not pinned"
+    );
+
+    Ok(())
+}
+
+/// The `move-to-workspace` command.
+fn move_to_workspace(niri: &mut Niri<impl Ipc>, index: u8, focus: FocusArgs) -> Result<()> {
+    let window = niri.window()?;
+    let workspaces = niri.workspaces()?;
+
+    let output = workspaces
+        .iter()
+        .find(|ws| Some(ws.id) == window.workspace_id)
+        .and_then(|ws| ws.output.as_ref());
+    ensure!(
+        workspaces
+            .iter()
+            .any(|ws| ws.idx == index && ws.output.as_ref() == output),
+        "there is no workspace with index {index} on the output of the window"
+    );
+
+    niri.action(Action::MoveWindowToWorkspace {
+        window_id: Some(window.id),
+        reference: WorkspaceReferenceArg::Index(index),
+        focus: false,
+    })?;
+    // niri's own `focus` only follows a window that is focused.
+    if focus.follow_window {
+        niri.action(Action::FocusWindow { id: window.id })?;
+    }
+
+    Ok(())
+}
+
+/// The `move-to-new-workspace` command.
+fn move_to_new_workspace(niri: &mut Niri<impl Ipc>, focus: FocusArgs) -> Result<()> {
+    let window = niri.window()?;
+    let workspaces = niri.workspaces()?;
+    let windows = niri.windows()?;
+
+    let output = workspaces
+        .iter()
+        .find(|ws| Some(ws.id) == window.workspace_id)
+        .and_then(|ws| ws.output.clone());
+
+    // niri always keeps an empty workspace after the others of each output, which
+    // becomes a new workspace as soon as a window is moved there.
+    let Some(target) = workspaces
+        .iter()
+        .filter(|ws| ws.output == output)
+        .max_by_key(|ws| ws.idx)
+        .filter(|ws| !windows.iter().any(|w| w.workspace_id == Some(ws.id)))
+    else {
+        bail!("there is no empty workspace at the end of the output of the window")
+    };
+
+    niri.action(Action::MoveWindowToWorkspace {
+        window_id: Some(window.id),
+        reference: WorkspaceReferenceArg::Id(target.id),
+        focus: false,
+    })?;
+    // niri's own `focus` only follows a window that is focused.
+    if focus.follow_window {
+        niri.action(Action::FocusWindow { id: window.id })?;
+    }
+
+    if !niri.dry_run() {
+        let windows = niri.windows()?;
+        ensure!(
+            windows
+                .iter()
+                .filter(|w| w.workspace_id == Some(target.id))
+                .map(|w| w.id)
+                .eq([window.id]),
+            "the window didn't end up alone on the new workspace"
+        );
+    }
+
+    Ok(())
+}
+
+/// The `floating-cycle` command.
+fn floating_cycle(niri: &mut Niri<impl Ipc>, global: &GlobalArgs) -> Result<()> {
+    let window = niri.floating_window()?;
+    let output = niri.target_output(&window)?;
+    let config = Config::for_args(global)?;
+
+    let cycle = config.cycle();
+    ensure!(!cycle.is_empty(), "the cycle in the config is empty");
+
+    let Some((x, y)) = absolute_position(&window, &niri.current_output(&window)?) else {
+        bail!("the window has no position")
+    };
+
+    let mut current = 0;
+    let mut min_distance = f64::INFINITY;
+    for (i, direction) in cycle.iter().enumerate() {
+        let (target_x, target_y) = snap_target(&window, direction, &output, &config)?;
+        let distance =
+            target_x.map_or(0., |tx| (tx - x).powi(2)) + target_y.map_or(0., |ty| (ty - y).powi(2));
+        if distance < min_distance {
+            current = i;
+            min_distance = distance;
+        }
+    }
+
+    let next = &cycle[(current + 1) % cycle.len()];
+    snap_floating_on(niri, &window, std::slice::from_ref(next), &output, &config)?;
+
+    Ok(())
+}
+
+/// The `floating-place` command.
+fn floating_place(
+    niri: &mut Niri<impl Ipc>,
+    global: &GlobalArgs,
+    x_pct: Option<f64>,
+    y_pct: Option<f64>,
+) -> Result<()> {
+    let window = niri.floating_window()?;
+    let output = niri.target_output(&window)?;
+    let config = Config::for_args(global)?;
+    let WorkArea {
+        x,
+        y,
+        width,
+        height,
+        ..
+    } = WorkArea::new(&output, &config)?;
+
+    let (tile_width, tile_height) = window.layout.tile_size;
+
+    niri.action(Action::MoveFloatingWindow {
+        id: Some(window.id),
+        x: x_pct
+            .map(|pct| PositionChange::SetFixed(x + pct / 100. * (width - tile_width)))
+            .unwrap_or(PositionChange::AdjustFixed(0.)),
+        y: y_pct
+            .map(|pct| PositionChange::SetFixed(y + pct / 100. * (height - tile_height)))
+            .unwrap_or(PositionChange::AdjustFixed(0.)),
+    })?;
+
+    Ok(())
+}
+
+/// The `debug` command.
+fn debug(niri: &mut Niri<impl Ipc>, global: &GlobalArgs, json: bool) -> Result<()> {
+    let window = niri.window()?;
+    let output = niri.target_output(&window)?;
+    let config = Config::for_args(global)?;
+
+    let Some(logical) = output.logical else {
+        bail!("the output has no logical geometry")
+    };
+    let margins = config.margins(&output.name);
+    let (width, height) = (logical.width as f64, logical.height as f64);
+    let area = WorkArea::new(&output, &config)?;
+
+    let mut snaps = Vec::new();
+    for direction in Direction::value_variants() {
+        let (x, y) = snap_target(&window, direction, &output, &config)?;
+        let name = direction
+            .to_possible_value()
+            .map(|v| v.get_name().to_string());
+        snaps.push((name.unwrap_or_default(), x, y));
+    }
+
+    if json {
+        let snaps: serde_json::Map<_, _> = snaps
+            .iter()
+            .map(|(name, x, y)| (name.clone(), serde_json::json!({ "x": x, "y": y })))
+            .collect();
+        let info = serde_json::json!({
+            "output": output.name,
+            "logical": logical,
+            "margins": {
+                "left": margins.left.resolve(width),
+                "right": margins.right.resolve(width),
+                "top": margins.top.resolve(height),
+                "bottom": margins.bottom.resolve(height),
+            },
+            "work_area": {
+                "x": area.x,
+                "y": area.y,
+                "width": area.width,
+                "height": area.height,
+            },
+            "window": window.id,
+            "tile_size": window.layout.tile_size,
+            "account_border": config.account_border,
+            "snap": snaps,
+        });
+        println!("{info}");
+    } else {
+        println!(
+            "output     {} {}x{}+{}+{} scale {}",
+            output.name, logical.width, logical.height, logical.x, logical.y, logical.scale
+        );
+        println!(
+            "margins    left {} right {} top {} bottom {}",
+            margins.left.resolve(width),
+            margins.right.resolve(width),
+            margins.top.resolve(height),
+            margins.bottom.resolve(height),
+        );
+        println!(
+            "work area  {}x{}+{}+{}",
+            area.width, area.height, area.x, area.y
+        );
+        let (tile_width, tile_height) = window.layout.tile_size;
+        println!(
+            "window     {} tile {tile_width}x{tile_height}{}",
+            window.id,
+            if config.account_border {
+                ", aligning the window instead of the tile"
+            } else {
+                ""
+            }
+        );
+        println!();
+        println!("{:<14} {:<10} Y", "DIRECTION", "X");
+        let show = |pos: Option<f64>| pos.map_or("-".to_string(), |pos| pos.to_string());
+        for (name, x, y) in snaps {
+            println!("{name:<14} {:<10} {}", show(x), show(y));
+        }
+    }
+
+    Ok(())
+}
+
+/// The `outputs` command.
+fn outputs(niri: &mut Niri<impl Ipc>, json: bool) -> Result<()> {
+    let outputs = niri.outputs()?;
+
+    if json {
+        println!("{}", serde_json::to_string(&outputs)?);
+    } else {
+        let focused = niri.focused_output()?.name;
+
+        let mut outputs: Vec<_> = outputs.into_values().collect();
+        outputs.sort_by(|a, b| a.name.cmp(&b.name));
+
+        println!(
+            "{:<12} {:<40} {:<22} {:<6} FOCUSED",
+            "NAME", "MAKE/MODEL", "LOGICAL", "SCALE"
+        );
+        for output in outputs {
+            let (logical, scale) = match output.logical {
+                Some(l) => (
+                    format!("{}x{}+{}+{}", l.width, l.height, l.x, l.y),
+                    l.scale.to_string(),
+                ),
+                None => (String::new(), String::new()),
+            };
+            println!(
+                "{:<12} {:<40} {:<22} {:<6} {}",
+                output.name,
+                format!("{} {}", output.make, output.model),
+                logical,
+                scale,
+                output.name == focused,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The `workspaces` command.
+fn workspaces(niri: &mut Niri<impl Ipc>, json: bool) -> Result<()> {
+    let mut workspaces = niri.workspaces()?;
+
+    if json {
+        println!("{}", serde_json::to_string(&workspaces)?);
+    } else {
+        let windows = niri.windows()?;
+        workspaces.sort_by(|a, b| a.output.cmp(&b.output).then(a.idx.cmp(&b.idx)));
+
+        println!(
+            "{:<12} {:<4} {:<16} {:<7} {:<8} WINDOWS",
+            "OUTPUT", "IDX", "NAME", "ACTIVE", "FOCUSED"
+        );
+        for ws in workspaces {
+            println!(
+                "{:<12} {:<4} {:<16} {:<7} {:<8} {}",
+                ws.output.as_deref().unwrap_or_default(),
+                ws.idx,
+                ws.name.as_deref().unwrap_or_default(),
+                ws.is_active,
+                ws.is_focused,
+                windows
+                    .iter()
+                    .filter(|w| w.workspace_id == Some(ws.id))
+                    .count(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The `focus-monitor` command.
+fn focus_monitor(niri: &mut Niri<impl Ipc>, name: String) -> Result<()> {
+    ensure!(
+        niri.outputs()?.contains_key(&name),
+        "there is no output named {name}"
+    );
+
+    niri.action(Action::FocusMonitor { output: name })?;
+
+    Ok(())
+}
+
+/// The `workspace-to-monitor` command.
+fn workspace_to_monitor(niri: &mut Niri<impl Ipc>, name: String) -> Result<()> {
+    ensure!(
+        niri.outputs()?.contains_key(&name),
+        "there is no output named {name}"
+    );
+    let Some(workspace) = niri.workspaces()?.into_iter().find(|ws| ws.is_focused) else {
+        bail!("no workspace is focused")
+    };
+    ensure!(
+        workspace.output.as_ref() != Some(&name),
+        "the workspace is already on {name}"
+    );
+
+    niri.action(Action::MoveWorkspaceToMonitor {
+        output: name,
+        reference: Some(WorkspaceReferenceArg::Id(workspace.id)),
+    })?;
+
+    Ok(())
+}
+
+/// The `move-to-monitor` command.
+fn move_to_monitor(niri: &mut Niri<impl Ipc>, name: String, focus: FocusArgs) -> Result<()> {
+    let window = niri.window()?;
+    let outputs = niri.outputs()?;
+    ensure!(
+        outputs.contains_key(&name),
+        "there is no output named {name}"
+    );
+    let from = niri.current_output(&window)?;
+
+    niri.action(Action::MoveWindowToMonitor {
+        id: Some(window.id),
+        output: name,
+    })?;
+
+    // The focus moves along with a focused window.
+    if focus.follow_window {
+        niri.action(Action::FocusWindow { id: window.id })?;
+    } else if window.is_focused {
+        niri.action(Action::FocusMonitor { output: from.name })?;
+    }
+
+    Ok(())
+}
+
+/// The `cycle-monitors` command.
+fn cycle_monitors(
+    niri: &mut Niri<impl Ipc>,
+    global: &GlobalArgs,
+    wrap: bool,
+    focus: FocusArgs,
+) -> Result<()> {
+    let window = niri.window()?;
+    let outputs = niri.outputs()?;
+    let from = niri.current_output(&window)?;
+
+    // Outputs that are off have no position and can't show windows anyway.
+    let mut order: Vec<_> = outputs
+        .values()
+        .filter_map(|o| o.logical.map(|l| (l.x, l.y, o)))
+        .collect();
+    order.sort_by(|a, b| (a.0, a.1, &a.2.name).cmp(&(b.0, b.1, &b.2.name)));
+
+    let Some(current) = order.iter().position(|(_, _, o)| o.name == from.name) else {
+        bail!("the output of the window is off")
+    };
+    let next = match order.get(current + 1) {
+        Some(next) => next,
+        None if wrap => &order[0],
+        None => bail!("the window is already on the last output"),
+    };
+    let to = next.2;
+
+    if window.is_floating {
+        let config = Config::for_args(global)?;
+        move_floating_to_output(niri, &window, &from, to, &config)?;
+    } else {
+        niri.action(Action::MoveWindowToMonitor {
+            id: Some(window.id),
+            output: to.name.clone(),
+        })?;
+    }
+
+    if focus.follow_window {
+        niri.action(Action::FocusWindow { id: window.id })?;
+    } else if window.is_focused {
+        niri.action(Action::FocusMonitor {
+            output: from.name.clone(),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// The `floating-to-output` command.
+fn floating_to_output(niri: &mut Niri<impl Ipc>, global: &GlobalArgs, name: String) -> Result<()> {
+    let window = niri.floating_window()?;
+    let config = Config::for_args(global)?;
+
+    let mut outputs = niri.outputs()?;
+    let from = niri.current_output(&window)?;
+    let Some(to) = outputs.remove(&name) else {
+        bail!("there is no output named {name}")
+    };
+
+    move_floating_to_output(niri, &window, &from, &to, &config)?;
+
+    Ok(())
+}
+
+/// The `version` command.
+fn version(niri: &mut Niri<impl Ipc>) -> Result<()> {
+    println!("myniri {}", env!("CARGO_PKG_VERSION"));
+    println!("niri {}", niri.version()?);
+
+    Ok(())
+}
+
+/// The `undo` command.
+fn undo(niri: &mut Niri<impl Ipc>) -> Result<()> {
+    let windows = niri.windows()?;
+    let mut entries = history::load()?;
+
+    // Windows that were closed or tiled since can't be moved back, so skip their entries.
+    let (entry, window) = loop {
+        let Some(entry) = entries.pop() else {
+            bail!("there is nothing to undo")
+        };
+        if let Some(window) = windows.iter().find(|w| w.id == entry.id && w.is_floating) {
+            break (entry, window);
+        }
+    };
+
+    niri.action_unrecorded(Action::MoveFloatingWindow {
+        id: Some(window.id),
+        x: PositionChange::SetFixed(entry.x),
+        y: PositionChange::SetFixed(entry.y),
+    })?;
+
+    if !niri.dry_run() {
+        history::save(&entries)?;
+    }
+
+    Ok(())
+}
+
+/// The `kb-layout` command.
+fn kb_layout(niri: &mut Niri<impl Ipc>, action: KbLayoutAction) -> Result<()> {
+    let layouts = match niri.request(Request::KeyboardLayouts)? {
+        Response::KeyboardLayouts(layouts) => layouts,
+        response => bail!("unexpected response to KeyboardLayouts: {response:?}"),
+    };
+
+    let layout = match action {
+        KbLayoutAction::Next => LayoutSwitchTarget::Next,
+        KbLayoutAction::Prev => LayoutSwitchTarget::Prev,
+        KbLayoutAction::Index { index } => {
+            ensure!(
+                (index as usize) < layouts.names.len(),
+                "there are only {} keyboard layouts",
+                layouts.names.len()
+            );
+            LayoutSwitchTarget::Index(index)
+        }
+        KbLayoutAction::Print => {
+            for (i, name) in layouts.names.iter().enumerate() {
+                let active = if i == layouts.current_idx as usize {
+                    '*'
+                } else {
+                    ' '
+                };
+                println!("{active} {i} {name}");
+            }
+            return Ok(());
+        }
+    };
+
+    niri.action(Action::SwitchLayout { layout })?;
+
+    Ok(())
+}
+
+/// The `output` command.
+fn output(niri: &mut Niri<impl Ipc>, name: String, action: OutputChange) -> Result<()> {
+    let Some(output) = niri.outputs()?.remove(&name) else {
+        bail!("there is no output named {name}")
+    };
+
+    let action = match action {
+        OutputChange::On => OutputAction::On,
+        OutputChange::Off => OutputAction::Off,
+        OutputChange::Scale { scale } => {
+            ensure!(
+                scale.is_finite() && scale > 0.,
+                "the scale must be a positive number"
+            );
+            OutputAction::Scale {
+                scale: ScaleToSet::Specific(scale),
+            }
+        }
+        OutputChange::Mode { mode } => OutputAction::Mode {
+            mode: ModeToSet::Specific(output_mode(&output, &mode)?),
+        },
+    };
+
+    send_raw(
+        niri,
+        Request::Output {
+            output: name,
+            action,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Run the commands or requests from stdin, see [`Command::Run`].
+fn run_script(global: &GlobalArgs, connection: &mut Option<Niri>, keep_going: bool) -> Result<()> {
+    let input = std::io::read_to_string(std::io::stdin())?;
+    let mut failed = 0;
+
+    if input.trim_start().starts_with('[') {
+        let requests: Vec<Request> =
+            serde_json::from_str(&input).context("failed to parse the requests")?;
+
+        let niri = connect(connection, global)?;
+        for (i, request) in requests.into_iter().enumerate() {
+            match send_raw(niri, request) {
+                Ok(response) => println!("{}", serde_json::to_string(&response)?),
+                Err(e) if keep_going => {
+                    eprintln!("request {}: {e:#}", i + 1);
+                    failed += 1;
+                }
+                Err(e) => return Err(e.context(format!("request {}", i + 1))),
+            }
+        }
+    } else {
+        for (i, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match run_line(line, global, connection) {
+                Ok(()) => (),
+                Err(e) if keep_going => {
+                    eprintln!("line {}: {e:#}", i + 1);
+                    failed += 1;
+                }
+                Err(e) => return Err(e.context(format!("line {}", i + 1))),
+            }
+        }
+    }
+
+    ensure!(failed == 0, "{failed} of the commands failed");
+
+    Ok(())
+}
+
+/// Send `request`, or only print it in dry-run mode if it changes anything in niri.
+fn send_raw(niri: &mut Niri<impl Ipc>, request: Request) -> Result<Response> {
+    match request {
+        Request::Action(action) => niri.action(action).map(|()| Response::Handled),
+        // Output changes are requests of their own rather than actions, so dry-run is handled
+        // here.
+        request @ Request::Output { .. } if niri.dry_run() => {
+            eprintln!("{request:?}");
+            Ok(Response::Handled)
+        }
+        request => niri.request(request),
+    }
+}
+
+fn run_line(line: &str, global: &GlobalArgs, connection: &mut Option<Niri>) -> Result<()> {
+    let words = shlex::split(line).ok_or_else(|| anyhow!("unbalanced quotes"))?;
+    let args = Args::try_parse_from(std::iter::once("myniri".to_string()).chain(words))?;
+
+    ensure!(
+        !matches!(args.command, Command::Run { .. }),
+        "run cannot be nested"
+    );
+
+    run(args.command, &args.global.or(global), connection)
+}
+
+/// The file to run for `program`, which is a path or a name to look up in `$PATH`, if it exists.
+fn executable(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return program.is_file().then(|| program.to_path_buf());
+    }
+
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|file| file.is_file())
+}
+
+/// The connection for running a command with the options in `args`, connecting on first use.
+fn connect<'a>(connection: &'a mut Option<Niri>, args: &GlobalArgs) -> Result<&'a mut Niri> {
+    let niri = match connection.take() {
+        Some(mut niri) => {
+            niri.configure(args);
+            niri
+        }
+        None => Niri::connect(args)?,
+    };
+
+    Ok(connection.insert(niri))
+}
+
+/// The mode of `output` given as `WIDTHxHEIGHT` or `WIDTHxHEIGHT@REFRESH`.
+///
+/// Without a refresh rate the highest one is used. A refresh rate matches a mode within half a
+/// hertz, so that `60` works for a mode of 59.951 Hz.
+fn output_mode(output: &Output, mode: &str) -> Result<ConfiguredMode> {
+    let invalid = || anyhow!("invalid mode {mode:?}, expected WIDTHxHEIGHT@REFRESH");
+
+    let (size, refresh) = match mode.split_once('@') {
+        Some((size, refresh)) => (size, Some(refresh.parse::<f64>().map_err(|_| invalid())?)),
+        None => (mode, None),
+    };
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let width: u16 = width.parse().map_err(|_| invalid())?;
+    let height: u16 = height.parse().map_err(|_| invalid())?;
+
+    let found = output
+        .modes
+        .iter()
+        .filter(|m| m.width == width && m.height == height)
+        .filter(|m| refresh.is_none_or(|r| (m.refresh_rate as f64 / 1000. - r).abs() < 0.5))
+        .max_by_key(|m| m.refresh_rate);
+    let Some(found) = found else {
+        let modes: Vec<_> = output
+            .modes
+            .iter()
+            .map(|m| {
+                format!(
+                    "{}x{}@{:.3}",
+                    m.width,
+                    m.height,
+                    m.refresh_rate as f64 / 1000.
+                )
+            })
+            .collect();
+        ensure!(!modes.is_empty(), "{} has no modes", output.name);
+        bail!(
+            "{} has no mode {mode}, it has {}",
+            output.name,
+            modes.join(", ")
+        )
+    };
+
+    Ok(ConfiguredMode {
+        width,
+        height,
+        refresh: Some(found.refresh_rate as f64 / 1000.),
+    })
+}
+
+/// Move the floating `window` from the output `from` to `to`, at the same relative position in
+/// the work area.
+fn move_floating_to_output(
+    niri: &mut Niri<impl Ipc>,
+    window: &Window,
+    from: &Output,
+    to: &Output,
+    config: &Config,
+) -> Result<()> {
+    let Some((x, y)) = window.layout.tile_pos_in_workspace_view else {
+        bail!("the window has no position")
+    };
+    let (tile_width, tile_height) = window.layout.tile_size;
+
+    let from_area = WorkArea::new(from, config)?;
+    let to_area = WorkArea::new(to, config)?;
+
+    // Fraction of the free space left of and above the window, clamped so that the window stays
+    // inside the work area of an output with a different size.
+    let fraction = |pos: f64, start: f64, free: f64| {
+        if free > 0. {
+            ((pos - start) / free).clamp(0., 1.)
+        } else {
+            0.
+        }
+    };
+    let fx = fraction(
+        from_area.origin.0 + x,
+        from_area.x,
+        from_area.width - tile_width,
+    );
+    let fy = fraction(
+        from_area.origin.1 + y,
+        from_area.y,
+        from_area.height - tile_height,
+    );
+
+    niri.action(Action::MoveWindowToMonitor {
+        id: Some(window.id),
+        output: to.name.clone(),
+    })?;
+    niri.action(Action::MoveFloatingWindow {
+        id: Some(window.id),
+        x: PositionChange::SetFixed(to_area.x + fx * (to_area.width - tile_width).max(0.)),
+        y: PositionChange::SetFixed(to_area.y + fy * (to_area.height - tile_height).max(0.)),
+    })?;
+
+    Ok(())
+}
+
+/// The floating windows on the active workspace of `output`, sorted by id.
+fn floating_windows_on(niri: &mut Niri<impl Ipc>, output: &Output) -> Result<Vec<Window>> {
+    let workspace = niri
+        .workspaces()?
+        .into_iter()
+        .find(|ws| ws.is_active && ws.output.as_ref() == Some(&output.name))
+        .ok_or_else(|| anyhow!("the output has no active workspace"))?;
+
+    let mut windows = niri.windows()?;
+    windows.retain(|w| w.is_floating && w.workspace_id == Some(workspace.id));
+    windows.sort_by_key(|w| w.id);
+
+    Ok(windows)
+}
+
+/// Move the floating `windows` into rows inside `area`, in the given order.
+fn spread(niri: &mut Niri<impl Ipc>, windows: &[Window], area: &WorkArea) -> Result<()> {
+    let WorkArea {
+        x: area_x,
+        y: area_y,
+        width: area_width,
+        height: area_height,
+        ..
+    } = *area;
+    let (mut x, mut y) = (area_x, area_y);
+    let mut row_height: f64 = 0.;
+    for window in windows {
+        let (width, height) = window.layout.tile_size;
+
+        if x > area_x && x + width > area_x + area_width {
+            x = area_x;
+            y += row_height;
+            row_height = 0.;
+        }
+
+        // Windows that don't fit anymore stay inside the work area, overlapping the last row.
+        let bottom = area_y + area_height - height;
+        niri.action(Action::MoveFloatingWindow {
+            id: Some(window.id),
+            x: PositionChange::SetFixed(x),
+            y: PositionChange::SetFixed(y.min(bottom).max(area_y)),
+        })?;
+
+        x += width;
+        row_height = row_height.max(height);
+    }
+
+    Ok(())
+}
+
+/// The line listing `window` for `pick`, as its id and a description separated by a tab.
+fn pick_line(window: &Window) -> String {
+    // A newline in the title would split the entry into two lines of the menu.
+    let title = window
+        .title
+        .as_deref()
+        .unwrap_or_default()
+        .replace('\n', " ");
+    format!(
+        "{}\t{} — {}",
+        window.id,
+        window.app_id.as_deref().unwrap_or_default(),
+        title
+    )
+}
+
+/// The id of the window in the line of [`pick_line`] that was picked, if any.
+fn picked_id(input: &str) -> Result<Option<u64>> {
+    let line = input.trim_start();
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    // Only the id before the first tab matters, whatever the title contains.
+    let id = line.split_once('\t').map_or(line, |(id, _)| id);
+    let id = id
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid window id {id:?}"))?;
+    Ok(Some(id))
+}
+
+/// Run `spawn` and wait up to `timeout` for a new window that `matches` what it returned, and
+/// return the id of the window, or `None` if none opened in time.
+///
+/// The app id is often set after a window opened, so new windows that don't match yet are checked
+/// again when they change.
+fn wait_for_new_window<T>(
+    global: &GlobalArgs,
+    timeout: Duration,
+    spawn: impl FnOnce() -> Result<T>,
+    matches: impl Fn(&Window, &T) -> bool,
+) -> Result<Option<u64>> {
+    // Listen before spawning so that the window can't open unnoticed.
+    let mut read_event = Niri::connect(global)?.into_events()?;
+    let (sender, events) = mpsc::channel();
+    thread::spawn(move || while sender.send(read_event()).is_ok() {});
+
+    let spawned = spawn()?;
+
+    let deadline = Instant::now() + timeout;
+    let mut existing = HashSet::new();
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let Ok(event) = events.recv_timeout(timeout) else {
+            return Ok(None);
+        };
+
+        match event? {
+            Event::WindowsChanged { windows } => {
+                existing.extend(windows.iter().map(|w| w.id));
+            }
+            Event::WindowOpenedOrChanged { window }
+                if !existing.contains(&window.id) && matches(&window, &spawned) =>
+            {
+                return Ok(Some(window.id));
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Focus the matching window with the lowest id, or return `false` if there is none.
+fn focus_first(niri: &mut Niri<impl Ipc>, matches: impl Fn(&Window) -> bool) -> Result<bool> {
+    let mut windows = niri.windows()?;
+    windows.retain(matches);
+    windows.sort_by_key(|w| w.id);
+
+    let Some(window) = windows.first() else {
+        return Ok(false);
+    };
+
+    if windows.len() > 1 {
+        eprintln!("{} windows matched, focusing {}", windows.len(), window.id);
+    }
+
+    niri.action(Action::FocusWindow { id: window.id })?;
+
+    Ok(true)
+}
+
+/// The size that is `pct` percent of `total`, kept between 1 and 100 percent.
+fn percent_size(pct: f64, total: u32) -> SizeChange {
+    SizeChange::SetFixed((total as f64 * pct.clamp(1., 100.) / 100.).round() as i32)
+}
+
+/// The center of the tile of `window`, relative to its workspace view.
+fn tile_center(window: &Window) -> Option<(f64, f64)> {
+    let (x, y) = window.layout.tile_pos_in_workspace_view?;
+    let (width, height) = window.layout.tile_size;
+    Some((x + width / 2., y + height / 2.))
+}
+
+/// The absolute position of the tile of `window`, which is shown on `output`.
+fn absolute_position(window: &Window, output: &Output) -> Option<(f64, f64)> {
+    let (x, y) = window.layout.tile_pos_in_workspace_view?;
+    Some(match output.logical {
+        Some(logical) => (logical.x as f64 + x, logical.y as f64 + y),
+        None => (x, y),
+    })
+}
+
+/// Move the floating `window` to the given `directions` of the output, see [`snap_targets`].
+fn snap_floating(
+    niri: &mut Niri<impl Ipc>,
+    window: &Window,
+    directions: &[Direction],
+    config: &Config,
+) -> Result<()> {
+    let output = niri.target_output(window)?;
+    snap_floating_on(niri, window, directions, &output, config)
+}
+
+/// Move the floating `window` to the given `directions` of `output`, see [`snap_targets`].
+fn snap_floating_on(
+    niri: &mut Niri<impl Ipc>,
+    window: &Window,
+    directions: &[Direction],
+    output: &Output,
+    config: &Config,
+) -> Result<()> {
+    let (x, y) = snap_targets(window, directions, output, config)?;
+    log::info!(
+        "snapping window {} to {directions:?}: x = {x:?}, y = {y:?}",
+        window.id
+    );
+
+    niri.action(Action::MoveFloatingWindow {
+        id: Some(window.id),
+        x: x.map(PositionChange::SetFixed)
+            .unwrap_or(PositionChange::AdjustFixed(0.)),
+        y: y.map(PositionChange::SetFixed)
+            .unwrap_or(PositionChange::AdjustFixed(0.)),
+    })?;
+
+    Ok(())
+}
+
+/// The position snapping `window` to all of `directions` moves it to, combining the axes that
+/// each of them changes as given by [`snap_target`].
+///
+/// Fails if two of the directions change the same axis.
+fn snap_targets(
+    window: &Window,
+    directions: &[Direction],
+    output: &Output,
+    config: &Config,
+) -> Result<(Option<f64>, Option<f64>)> {
+    let mut x: Option<(f64, &Direction)> = None;
+    let mut y: Option<(f64, &Direction)> = None;
+
+    for direction in directions {
+        let (target_x, target_y) = snap_target(window, direction, output, config)?;
+
+        for (axis, target, name) in [
+            (&mut x, target_x, "horizontal"),
+            (&mut y, target_y, "vertical"),
+        ] {
+            let Some(target) = target else {
+                continue;
+            };
+            if let Some((_, other)) = axis {
+                bail!("{other:?} and {direction:?} both change the {name} position");
+            }
+            *axis = Some((target, direction));
+        }
+    }
+
+    Ok((x.map(|(x, _)| x), y.map(|(y, _)| y)))
+}
+
+/// The absolute position snapping `window` to `direction` of `output` moves it to, for the axes
+/// that `direction` changes.
+fn snap_target(
+    window: &Window,
+    direction: &Direction,
+    output: &Output,
+    config: &Config,
+) -> Result<(Option<f64>, Option<f64>)> {
+    let area = WorkArea::new(output, config)?;
+
+    // The rectangle aligned with the margins, as its size and its offset from the tile, which is
+    // what gets moved.
+    let (rect, (offset_x, offset_y)) = if config.account_border {
+        let (w, h) = window.layout.window_size;
+        ((w as f64, h as f64), window.layout.window_offset_in_tile)
+    } else {
+        (window.layout.tile_size, (0., 0.))
+    };
+
+    let (x, y): (Option<f64>, Option<f64>) = match direction {
+        Direction::Left => (Some(area.left()), None),
+        Direction::Down => (None, Some(area.bottom(rect.1))),
+        Direction::Up => (None, Some(area.top())),
+        Direction::Right => (Some(area.right(rect.0)), None),
+        Direction::Center => {
+            let (x, y) = area.center(rect);
+            (Some(x), Some(y))
+        }
+        Direction::TopLeft => (Some(area.left()), Some(area.top())),
+        Direction::TopRight => (Some(area.right(rect.0)), Some(area.top())),
+        Direction::BottomLeft => (Some(area.left()), Some(area.bottom(rect.1))),
+        Direction::BottomRight => (Some(area.right(rect.0)), Some(area.bottom(rect.1))),
+    };
+
+    Ok((
+        x.map(|x| area.round_x(x - offset_x)),
+        y.map(|y| area.round_y(y - offset_y)),
+    ))
+}
+
+/// The area of an output inside its margins, which floating windows are placed in.
+///
+/// Positions are absolute, like those of [`Action::MoveFloatingWindow`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct WorkArea {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    /// Position of the output, where its physical pixel grid starts
+    origin: (f64, f64),
+    scale: f64,
+}
+
+impl WorkArea {
+    fn new(output: &Output, config: &Config) -> Result<Self> {
+        let Some(logical) = output.logical else {
+            bail!("the output has no logical geometry")
+        };
+        let margins = config.margins(&output.name);
+        let origin = (logical.x as f64, logical.y as f64);
+        let (width, height) = (logical.width as f64, logical.height as f64);
+
+        let left = margins.left.resolve(width);
+        let right = margins.right.resolve(width);
+        let top = margins.top.resolve(height);
+        let bottom = margins.bottom.resolve(height);
+
+        Ok(Self {
+            x: origin.0 + left,
+            y: origin.1 + top,
+            width: width - left - right,
+            height: height - top - bottom,
+            origin,
+            scale: logical.scale,
+        })
+    }
+
+    /// The x of a tile at the left margin.
+    fn left(&self) -> f64 {
+        self.x
+    }
+
+    /// The x of a tile `width` wide at the right margin.
+    ///
+    /// A tile wider than the area is kept at the left margin rather than going off the left of
+    /// the output.
+    fn right(&self, width: f64) -> f64 {
+        (self.x + self.width - width).max(self.x)
+    }
+
+    /// The y of a tile at the top margin.
+    fn top(&self) -> f64 {
+        self.y
+    }
+
+    /// The y of a tile `height` high at the bottom margin, kept at the top margin like
+    /// [`Self::right`].
+    fn bottom(&self, height: f64) -> f64 {
+        (self.y + self.height - height).max(self.y)
+    }
+
+    /// The position of a tile of the given size in the middle of the area.
+    fn center(&self, (width, height): (f64, f64)) -> (f64, f64) {
+        (
+            (self.left() + self.right(width)) / 2.,
+            (self.top() + self.bottom(height)) / 2.,
+        )
+    }
+
+    /// Round the x position `x` to the physical pixel grid of the output.
+    ///
+    /// niri rounds positions to physical pixels in its own way, which can leave a gap of a pixel
+    /// at the edge, so they are better rounded here already.
+    fn round_x(&self, x: f64) -> f64 {
+        self.origin.0 + ((x - self.origin.0) * self.scale).round() / self.scale
+    }
+
+    /// Round the y position `y` like [`Self::round_x`].
+    fn round_y(&self, y: f64) -> f64 {
+        self.origin.1 + ((y - self.origin.1) * self.scale).round() / self.scale
+    }
+}
+
+/// Resize and move the floating `window` so that its tile covers the given rectangle.
+fn fit_floating(
+    niri: &mut Niri<impl Ipc>,
+    window: &Window,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+) -> Result<()> {
+    resize_tile(niri, window, width, height)?;
+    niri.action(Action::MoveFloatingWindow {
+        id: Some(window.id),
+        x: PositionChange::SetFixed(x),
+        y: PositionChange::SetFixed(y),
+    })?;
+
+    Ok(())
+}
+
+/// Resize the floating `window` so that its tile is `width` by `height`.
+fn resize_tile(niri: &mut Niri<impl Ipc>, window: &Window, width: f64, height: f64) -> Result<()> {
+    // The size actions set the size of the window itself, which excludes the borders of the tile.
+    let border_width = window.layout.tile_size.0 - window.layout.window_size.0 as f64;
+    let border_height = window.layout.tile_size.1 - window.layout.window_size.1 as f64;
+
+    niri.action(Action::SetWindowWidth {
+        id: Some(window.id),
+        change: SizeChange::SetFixed((width - border_width).round() as i32),
+    })?;
+    niri.action(Action::SetWindowHeight {
+        id: Some(window.id),
+        change: SizeChange::SetFixed((height - border_height).round() as i32),
+    })?;
+
+    Ok(())
+}
+
+/// Consume the focused tiled window into the column next to it `count` times.
+///
+/// The focus ends up where `args.focus` says.
+fn consume_into(niri: &mut Niri<impl Ipc>, side: Side, args: &ConsumeArgs) -> Result<()> {
+    let outcome = consume(niri, side, args)?;
+    if args.json {
+        println!("{}", serde_json::to_string(&outcome)?);
+    }
+
+    Ok(())
+}
+
+/// Do what [`consume_into`] does, returning what was done instead of printing it.
+fn consume(niri: &mut Niri<impl Ipc>, side: Side, args: &ConsumeArgs) -> Result<ConsumeOutcome> {
+    let count = args.count;
+    let mut window = niri.tiled_window()?;
+    let focused = if window.is_focused {
+        Some(window.id)
+    } else {
+        focused_window_id(niri)?
+    };
+    let original_row = window.layout.pos_in_scrolling_layout.map(|(_, row)| row);
+
+    let mut outcome = ConsumeOutcome {
+        consumed_window: window.id,
+        from_column: window
+            .layout
+            .pos_in_scrolling_layout
+            .map(|(column, _)| column),
+        consumes: 0,
+        moves_up: 0,
+        moves_down: 0,
+    };
+
+    // niri puts windows consumed into left at the bottom of the column, so their next position is
+    // computed from the column heights instead of querying the window again after every step.
+    let mut heights = HashMap::new();
+    if let Side::Left = side
+        && count > 1
+    {
+        for w in niri.windows()? {
+            if w.workspace_id == window.workspace_id
+                && let Some((column, _)) = w.layout.pos_in_scrolling_layout
+            {
+                *heights.entry(column).or_insert(0) += 1;
+            }
+        }
+    }
+
+    for i in 0..count {
+        if i > 0 {
+            niri.action(Action::FocusWindow { id: window.id })?;
+            if let Side::Right = side {
+                window = niri.tiled_window()?;
+            }
+        }
+
+        // This is checked before focusing the window, so that failing leaves the focus alone.
+        if !has_column_beside(niri, &window, side)? {
+            let edge = match side {
+                Side::Left => "first column into left",
+                Side::Right => "last column into right",
+            };
+            ensure!(i > 0, "cannot consume a window in the {edge}");
+            eprintln!("stopped after {i} of {count} consumes: reached the {edge}");
+            break;
+        }
+
+        // The actions used for consuming act on the focused window.
+        if i == 0 && !window.is_focused {
+            niri.action(Action::FocusWindow { id: window.id })?;
+        }
+        consume_once(niri, &window, side, &mut outcome)?;
+
+        if let Side::Left = side
+            && let Some((column, row)) = &mut window.layout.pos_in_scrolling_layout
+        {
+            *column -= 1;
+            let height = heights.entry(*column).or_insert(0);
+            *height += 1;
+            *row = *height;
+        }
+    }
+
+    niri.action(Action::FocusWindow { id: window.id })?;
+
+    if let Some(row) = args.row {
+        let (up, down) = move_to_row(niri, window.id, row, original_row)?;
+        outcome.moves_up += up;
+        outcome.moves_down += down;
+    }
+
+    // The window is focused for the moves above, so only another window needs to get it back.
+    if let Some(id) = args.focus.target(focused, window.id)
+        && id != window.id
+    {
+        niri.action(Action::FocusWindow { id })?;
+    }
+
+    Ok(outcome)
+}
+
+/// Swap the column of the tiled window with the column next to it.
+///
+/// The focus ends up where `focus` says.
+fn swap_column(niri: &mut Niri<impl Ipc>, side: Side, focus: FocusArgs) -> Result<()> {
+    let window = niri.tiled_window()?;
+    let Some((column, _)) = window.layout.pos_in_scrolling_layout else {
+        bail!("the window is not in a column")
+    };
+
+    let last = niri
+        .windows()?
+        .iter()
+        .filter(|w| w.workspace_id == window.workspace_id)
+        .filter_map(|w| w.layout.pos_in_scrolling_layout)
+        .map(|(c, _)| c)
+        .max()
+        .unwrap_or(column);
+    match side {
+        Side::Left => ensure!(column > 1, "the column is already the first one"),
+        Side::Right => ensure!(column < last, "the column is already the last one"),
+    }
+
+    // The actions for moving columns act on the focused column.
+    let focused = focused_window_id(niri)?;
+    if focused != Some(window.id) {
+        niri.action(Action::FocusWindow { id: window.id })?;
+    }
+
+    niri.action(match side {
+        Side::Left => Action::MoveColumnLeft {},
+        Side::Right => Action::MoveColumnRight {},
+    })?;
+
+    niri.action(Action::FocusWindow {
+        id: focus.target(focused, window.id).unwrap_or(window.id),
+    })?;
+
+    Ok(())
+}
+
+/// The id of the focused window, or `None` if no window is focused.
+fn focused_window_id(niri: &mut Niri<impl Ipc>) -> Result<Option<u64>> {
+    match niri.focused_window() {
+        Ok(focused) => Ok(Some(focused.id)),
+        Err(e) if matches!(e.downcast_ref(), Some(Failure::NoFocusedWindow)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Move the focused window with `id` up or down to `row` of its column, and return how many times
+/// it was moved up and down.
+fn move_to_row(
+    niri: &mut Niri<impl Ipc>,
+    id: u64,
+    row: Row,
+    original_row: Option<usize>,
+) -> Result<(usize, usize)> {
+    let windows = niri.windows()?;
+    let Some(window) = windows.iter().find(|w| w.id == id) else {
+        bail!("the window {id} disappeared")
+    };
+    let Some((column, current)) = window.layout.pos_in_scrolling_layout else {
+        return Ok((0, 0));
+    };
+
+    let len = windows
+        .iter()
+        .filter(|w| w.workspace_id == window.workspace_id)
+        .filter_map(|w| w.layout.pos_in_scrolling_layout)
+        .filter(|&(c, _)| c == column)
+        .count();
+
+    let target = match row {
+        Row::Top => 1,
+        Row::Bottom => len,
+        Row::Keep => original_row.unwrap_or(current).min(len),
+    };
+
+    for _ in target..current {
+        niri.action(Action::MoveWindowUp {})?;
+    }
+    for _ in current..target {
+        niri.action(Action::MoveWindowDown {})?;
+    }
+
+    Ok((
+        current.saturating_sub(target),
+        target.saturating_sub(current),
+    ))
+}
+
+/// Whether there is a column on `side` of the column of `window` to consume it into.
+fn has_column_beside(niri: &mut Niri<impl Ipc>, window: &Window, side: Side) -> Result<bool> {
+    let Some((column, _)) = window.layout.pos_in_scrolling_layout else {
+        return Ok(true);
+    };
+
+    Ok(match side {
+        Side::Left => column > 1,
+        Side::Right => niri
+            .windows()?
+            .iter()
+            .filter(|w| w.workspace_id == window.workspace_id)
+            .filter_map(|w| w.layout.pos_in_scrolling_layout)
+            .any(|(c, _)| c > column),
+    })
+}
+
+/// Consume the focused `window` into the column next to it, see [`has_column_beside`].
+///
+/// The actions sent are counted in `outcome`.
+fn consume_once(
+    niri: &mut Niri<impl Ipc>,
+    window: &Window,
+    side: Side,
+    outcome: &mut ConsumeOutcome,
+) -> Result<()> {
+    match side {
+        Side::Left => {
+            // Consuming into left first moves the window to the top of its column.
+            if let Some((_, row)) = window.layout.pos_in_scrolling_layout {
+                for _ in 1..row {
+                    niri.action(Action::MoveWindowUp {})?;
+                    outcome.moves_up += 1;
+                }
+            }
+
+            niri.action(Action::FocusColumnLeft {})?;
+            niri.action(Action::ConsumeWindowIntoColumn {})?;
+        }
+        Side::Right => {
+            // `ConsumeWindowIntoColumn` always pulls from the column on the right, so it cannot be
+            // mirrored. Instead, make the window a column of its own and consume it rightwards.
+            if let Some((column, _)) = window.layout.pos_in_scrolling_layout {
+                let shared = niri
+                    .windows()?
+                    .iter()
+                    .filter(|w| w.workspace_id == window.workspace_id)
+                    .filter_map(|w| w.layout.pos_in_scrolling_layout)
+                    .filter(|&(c, _)| c == column)
+                    .count()
+                    > 1;
+                if shared {
+                    niri.action(Action::ConsumeOrExpelWindowRight {
+                        id: Some(window.id),
+                    })?;
+                }
+            }
+
+            niri.action(Action::ConsumeOrExpelWindowRight {
+                id: Some(window.id),
+            })?;
+        }
+    }
+    outcome.consumes += 1;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipc::mock::Mock;
+    use niri_ipc::{LogicalOutput, Transform, WindowLayout, Workspace};
+
+    fn floating_window(tile_size: (f64, f64)) -> Window {
+        Window {
+            id: 1,
+            title: None,
+            app_id: None,
+            pid: None,
+            workspace_id: Some(1),
+            is_focused: true,
+            is_floating: true,
+            is_urgent: false,
+            layout: WindowLayout {
+                pos_in_scrolling_layout: None,
+                tile_size,
+                window_size: (tile_size.0 as i32, tile_size.1 as i32),
+                tile_pos_in_workspace_view: Some((0., 0.)),
+                window_offset_in_tile: (0., 0.),
+            },
+        }
+    }
+
+    fn output(x: i32, y: i32, width: u32, height: u32, scale: f64) -> Output {
+        Output {
+            name: "DP-1".to_string(),
+            make: String::new(),
+            model: String::new(),
+            serial: None,
+            physical_size: None,
+            modes: Vec::new(),
+            current_mode: None,
+            vrr_supported: false,
+            vrr_enabled: false,
+            logical: Some(LogicalOutput {
+                x,
+                y,
+                width,
+                height,
+                scale,
+                transform: Transform::Normal,
+            }),
+        }
+    }
+
+    fn tiled_window(id: u64, column: usize, row: usize) -> Window {
+        let mut window = floating_window((400., 300.));
+        window.id = id;
+        window.is_focused = false;
+        window.is_floating = false;
+        window.layout.pos_in_scrolling_layout = Some((column, row));
+        window
+    }
+
+    fn workspace(id: u64, output: &str) -> Workspace {
+        Workspace {
+            id,
+            idx: id as u8,
+            name: None,
+            output: Some(output.to_string()),
+            is_urgent: false,
+            is_active: true,
+            is_focused: true,
+            active_window_id: None,
+        }
+    }
+
+    fn consume_args(count: usize, row: Option<Row>) -> ConsumeArgs {
+        ConsumeArgs {
+            count,
+            row,
+            json: true,
+            focus: FocusArgs {
+                follow_window: false,
+                focus_stays: false,
+            },
+        }
+    }
+
+    /// Snap a 400x300 tile on a 1920x1080 output right of the origin with the default margins.
+    fn snap(direction: Direction) -> (PositionChange, PositionChange) {
+        snap_tile(direction, (400., 300.))
+    }
+
+    /// Snap a tile of the given size on the output of [`snap`].
+    fn snap_tile(direction: Direction, tile_size: (f64, f64)) -> (PositionChange, PositionChange) {
+        let window = floating_window(tile_size);
+        let mut mock = Mock {
+            windows: vec![window.clone()],
+            output: Some(output(1920, 0, 1920, 1080, 1.)),
+            ..Default::default()
+        };
+
+        let mut niri = Niri::new(&mut mock, &GlobalArgs::default());
+        snap_floating(&mut niri, &window, &[direction], &Config::default()).unwrap();
+
+        match mock.actions.as_slice() {
+            [Action::MoveFloatingWindow { id: Some(1), x, y }] => (*x, *y),
+            actions => panic!("unexpected actions {actions:?}"),
+        }
+    }
+
+    #[test]
+    fn snap_edges() {
+        use PositionChange::{AdjustFixed, SetFixed};
+
+        assert_eq!(snap(Direction::Left), (SetFixed(1920.), AdjustFixed(0.)));
+        assert_eq!(snap(Direction::Right), (SetFixed(3440.), AdjustFixed(0.)));
+        assert_eq!(snap(Direction::Up), (AdjustFixed(0.), SetFixed(0.)));
+        assert_eq!(snap(Direction::Down), (AdjustFixed(0.), SetFixed(732.)));
+    }
+
+    #[test]
+    fn snap_center_and_corners() {
+        use PositionChange::SetFixed;
+
+        assert_eq!(snap(Direction::Center), (SetFixed(2680.), SetFixed(366.)));
+        assert_eq!(snap(Direction::TopLeft), (SetFixed(1920.), SetFixed(0.)));
+        assert_eq!(snap(Direction::TopRight), (SetFixed(3440.), SetFixed(0.)));
+        assert_eq!(
+            snap(Direction::BottomLeft),
+            (SetFixed(1920.), SetFixed(732.))
+        );
+        assert_eq!(
+            snap(Direction::BottomRight),
+            (SetFixed(3440.), SetFixed(732.))
+        );
+    }
+
+    #[test]
+    fn snap_oversized() {
+        use PositionChange::{AdjustFixed, SetFixed};
+
+        let size = (2000., 1200.);
+        assert_eq!(
+            snap_tile(Direction::Right, size),
+            (SetFixed(1920.), AdjustFixed(0.))
+        );
+        assert_eq!(
+            snap_tile(Direction::Down, size),
+            (AdjustFixed(0.), SetFixed(0.))
+        );
+        assert_eq!(
+            snap_tile(Direction::BottomRight, size),
+            (SetFixed(1920.), SetFixed(0.))
+        );
+    }
+
+    #[test]
+    fn snap_to_scaled_pixels() {
+        // 1920 physical pixels wide at a scale of 1.5.
+        let output = output(0, 0, 1280, 720, 1.5);
+        let window = floating_window((401., 300.));
+
+        let config = Config::default();
+        let (x, _) = snap_target(&window, &Direction::Right, &output, &config).unwrap();
+        let x = x.unwrap();
+
+        let physical_x = x * 1.5;
+        assert_eq!(physical_x, physical_x.round());
+        // The tile reaches the edge of the output, going over it by less than a physical pixel.
+        let gap = (1280. - (x + 401.)) * 1.5;
+        assert!(gap < 1e-9 && gap > -1., "gap of {gap} physical pixels");
+    }
+
+    #[test]
+    fn work_area_edges() {
+        // The default margins leave 48 pixels at the bottom.
+        let area = WorkArea::new(&output(1920, 0, 1920, 1080, 1.), &Config::default()).unwrap();
+
+        assert_eq!(area.left(), 1920.);
+        assert_eq!(area.right(400.), 3440.);
+        assert_eq!(area.top(), 0.);
+        assert_eq!(area.bottom(300.), 732.);
+        assert_eq!(area.center((400., 300.)), (2680., 366.));
+    }
+
+    #[test]
+    fn work_area_clamps_oversized() {
+        let area = WorkArea::new(&output(0, 100, 1920, 1080, 1.), &Config::default()).unwrap();
+
+        assert_eq!(area.right(2000.), 0.);
+        assert_eq!(area.bottom(1200.), 100.);
+        assert_eq!(area.center((2000., 1200.)), (0., 100.));
+    }
+
+    #[test]
+    fn work_area_rounds_from_the_output() {
+        let area = WorkArea::new(&output(1280, 0, 1280, 720, 1.5), &Config::default()).unwrap();
+
+        // The grid of physical pixels starts at the output, 2/3 of a logical pixel apart.
+        assert_eq!(area.round_x(1280.), 1280.);
+        assert!((area.round_x(1280.4) - (1280. + 2. / 3.)).abs() < 1e-9);
+        assert!(area.round_y(0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn work_area_percent_margins() {
+        let config: Config =
+            toml::from_str("[margins]\nleft = \"5%\"\nbottom = \"10%\"\ntop = 20").unwrap();
+        let area = WorkArea::new(&output(0, 0, 1920, 1080, 1.), &config).unwrap();
+
+        assert_eq!((area.x, area.y), (96., 20.));
+        assert_eq!((area.width, area.height), (1824., 952.));
+    }
+
+    #[test]
+    fn margin_tables_merge_by_side() {
+        let config: Config = toml::from_str(
+            "[margins]\nleft = 5\nbottom = 10\n[margins.default]\ntop = 20\n[margins.DP-1]\nleft = 30",
+        )
+        .unwrap();
+
+        let margins = config.margins("DP-1");
+        assert_eq!(
+            [margins.left, margins.right, margins.top, margins.bottom],
+            [30., 0., 20., 10.].map(config::Margin::Pixels)
+        );
+        let margins = config.margins("DP-2");
+        assert_eq!(margins.left, config::Margin::Pixels(5.));
+
+        assert!(toml::from_str::<Config>("[margins.DP-1]\nbotom = 10").is_err());
+        assert!(toml::from_str::<Config>("[margins]\nbotom = 10").is_err());
+    }
+
+    #[test]
+    fn parse_margin_args() {
+        use config::{Margin, MarginSide};
+
+        assert_eq!(
+            config::parse_margin_arg("bottom=48"),
+            Ok((MarginSide::Bottom, Margin::Pixels(48.)))
+        );
+        assert_eq!(
+            config::parse_margin_arg("left=5%"),
+            Ok((MarginSide::Left, Margin::Percent(5.)))
+        );
+        assert!(config::parse_margin_arg("48").is_err());
+        assert!(config::parse_margin_arg("middle=48").is_err());
+        assert!(config::parse_margin_arg("top=high").is_err());
+    }
+
+    #[test]
+    fn snap_directions_on_the_same_axis_conflict() {
+        let window = floating_window((400., 300.));
+        let output = output(1920, 0, 1920, 1080, 1.);
+        let targets = |directions: &[Direction]| {
+            snap_targets(&window, directions, &output, &Config::default())
+        };
+
+        assert_eq!(
+            targets(&[Direction::Left, Direction::Up]).unwrap(),
+            (Some(1920.), Some(0.))
+        );
+        let e = targets(&[Direction::Left, Direction::Right]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Left and Right both change the horizontal position"
+        );
+        let e = targets(&[Direction::TopLeft, Direction::Down]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "TopLeft and Down both change the vertical position"
+        );
+    }
+
+    #[test]
+    fn consume_left_counts_what_it_did() {
+        use Action::{ConsumeWindowIntoColumn, FocusColumnLeft, FocusWindow, MoveWindowUp};
+
+        let mut window = tiled_window(1, 3, 2);
+        window.is_focused = true;
+        let mut mock = Mock {
+            windows: vec![
+                tiled_window(2, 1, 1),
+                tiled_window(3, 2, 1),
+                tiled_window(4, 3, 1),
+                window.clone(),
+            ],
+            // Consumed twice into left, the window is at the bottom of the first column.
+            windows_after_action: Some(vec![
+                tiled_window(2, 1, 1),
+                tiled_window(1, 1, 2),
+                tiled_window(3, 2, 1),
+                tiled_window(4, 3, 1),
+            ]),
+            ..Default::default()
+        };
+
+        let mut niri = Niri::new(&mut mock, &GlobalArgs::default());
+        let outcome = consume(&mut niri, Side::Left, &consume_args(2, Some(Row::Top))).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&outcome).unwrap(),
+            serde_json::json!({
+                "consumed_window": 1,
+                "from_column": 3,
+                "consumes": 2,
+                "moves_up": 3,
+                "moves_down": 0,
+            })
+        );
+        let expected = [
+            MoveWindowUp {},
+            FocusColumnLeft {},
+            ConsumeWindowIntoColumn {},
+            FocusWindow { id: 1 },
+            MoveWindowUp {},
+            FocusColumnLeft {},
+            ConsumeWindowIntoColumn {},
+            FocusWindow { id: 1 },
+            MoveWindowUp {},
+        ];
+        assert_eq!(format!("{:?}", mock.actions), format!("{expected:?}"));
+    }
+
+    #[test]
+    fn consume_stops_at_the_first_column() {
+        let mut window = tiled_window(1, 2, 1);
+        window.is_focused = true;
+        let mut mock = Mock {
+            windows: vec![tiled_window(2, 1, 1), window],
+            ..Default::default()
+        };
+
+        let mut niri = Niri::new(&mut mock, &GlobalArgs::default());
+        let outcome = consume(&mut niri, Side::Left, &consume_args(3, None)).unwrap();
+
+        assert_eq!((outcome.consumes, outcome.moves_up), (1, 0));
+        assert_eq!(mock.actions.len(), 4, "{:?}", mock.actions);
+    }
+
+    #[test]
+    fn consume_in_the_first_column_leaves_the_focus_alone() {
+        let mut focused = tiled_window(2, 2, 1);
+        focused.is_focused = true;
+        let mut mock = Mock {
+            windows: vec![tiled_window(1, 1, 1), focused],
+            ..Default::default()
+        };
+        let args = GlobalArgs {
+            window_id: Some(1),
+            ..Default::default()
+        };
+
+        let mut niri = Niri::new(&mut mock, &args);
+        assert!(consume(&mut niri, Side::Left, &consume_args(1, None)).is_err());
+        assert!(mock.actions.is_empty(), "{:?}", mock.actions);
+    }
+
+    #[test]
+    fn swap_floating_windows_on_an_output() {
+        let window = floating_window((400., 300.));
+        let mut other = floating_window((400., 300.));
+        other.id = 2;
+        other.is_focused = false;
+        other.layout.tile_pos_in_workspace_view = Some((100., 200.));
+        let mut mock = Mock {
+            windows: vec![window, other],
+            workspaces: vec![workspace(1, "DP-1")],
+            output: Some(output(1920, 0, 1920, 1080, 1.)),
+            ..Default::default()
+        };
+
+        let mut niri = Niri::new(&mut mock, &GlobalArgs::default());
+        floating_swap(&mut niri, 2).unwrap();
+
+        let moves: Vec<_> = mock
+            .actions
+            .iter()
+            .map(|action| match action {
+                Action::MoveFloatingWindow {
+                    id: Some(id),
+                    x: PositionChange::SetFixed(x),
+                    y: PositionChange::SetFixed(y),
+                } => (*id, *x, *y),
+                action => panic!("unexpected action {action:?}"),
+            })
+            .collect();
+        assert_eq!(moves, [(1, 2020., 200.), (2, 1920., 0.)]);
+    }
+
+    #[test]
+    fn output_modes() {
+        let mut output = output(0, 0, 1920, 1080, 1.);
+        output.modes = [
+            (1920, 1080, 60_000),
+            (1920, 1080, 143_981),
+            (2560, 1440, 59_951),
+        ]
+        .map(|(width, height, refresh_rate)| niri_ipc::Mode {
+            width,
+            height,
+            refresh_rate,
+            is_preferred: false,
+        })
+        .to_vec();
+        let mode = |mode| output_mode(&output, mode).map(|m| (m.width, m.height, m.refresh));
+
+        assert_eq!(mode("1920x1080").unwrap(), (1920, 1080, Some(143.981)));
+        assert_eq!(mode("1920x1080@60").unwrap(), (1920, 1080, Some(60.)));
+        assert_eq!(mode("2560x1440@60").unwrap(), (2560, 1440, Some(59.951)));
+        assert!(mode("1280x720").is_err());
+        assert!(mode("1920x1080@fast").is_err());
+        assert!(mode("1920").is_err());
+    }
+
+    #[test]
+    fn pick_ids_with_tabs_in_titles() {
+        let mut window = floating_window((400., 300.));
+        window.id = 42;
+        window.title = Some("a\ttitle\nwith tabs".to_string());
+        let line = pick_line(&window);
+        assert_eq!(line.lines().count(), 1);
+
+        assert_eq!(picked_id(&format!("{line}\n")).unwrap(), Some(42));
+        assert_eq!(picked_id("  7\n").unwrap(), Some(7));
+        assert_eq!(picked_id("\n").unwrap(), None);
+        assert!(picked_id("firefox\t7").is_err());
+    }
+}
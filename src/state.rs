@@ -0,0 +1,31 @@
+use anyhow::{Context, Result};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{fs, path::PathBuf};
+
+use crate::config;
+
+/// Path of the state file `name` in [`config::state_dir`], creating the directory if needed.
+pub fn path(name: &str) -> Result<PathBuf> {
+    let dir = config::state_dir()?;
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    Ok(dir.join(name))
+}
+
+/// Read the JSON state file `name`, or the default value if it doesn't exist yet.
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> Result<T> {
+    let path = path(name)?;
+
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// Write `value` to the JSON state file `name`.
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<()> {
+    let path = path(name)?;
+    let content = serde_json::to_string(value)?;
+    fs::write(&path, content).with_context(|| format!("failed to write {}", path.display()))
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use niri_ipc::state::{EventStreamState, EventStreamStatePart};
+use serde_json::json;
+use std::{
+    io::{self, Write, stdout},
+    ops::ControlFlow,
+};
+
+use crate::{GlobalArgs, ipc::Niri};
+
+/// Print the status as a line of JSON for waybar whenever it changes, reconnecting whenever the
+/// connection drops.
+pub fn run(args: &GlobalArgs) -> Result<()> {
+    let mut state = EventStreamState::default();
+    let mut last = None;
+
+    Niri::for_each_event_reconnecting(args, |_, event| {
+        state.apply(event);
+
+        let line = line(&state).to_string();
+        if last.as_ref() == Some(&line) {
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        let mut stdout = stdout().lock();
+        match writeln!(stdout, "{line}").and_then(|()| stdout.flush()) {
+            Ok(()) => (),
+            // Nobody reads the status anymore, like when waybar quit.
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(ControlFlow::Break(())),
+            Err(e) => return Err(e.into()),
+        }
+        last = Some(line);
+
+        Ok(ControlFlow::Continue(()))
+    })
+}
+
+/// The waybar module for `state`: the focused workspace and the app id of the focused window.
+fn line(state: &EventStreamState) -> serde_json::Value {
+    let workspace = state
+        .workspaces
+        .workspaces
+        .values()
+        .find(|ws| ws.is_focused);
+    let window = state.windows.windows.values().find(|w| w.is_focused);
+
+    let workspace_text = workspace.map(|ws| match &ws.name {
+        Some(name) => name.clone(),
+        None => ws.idx.to_string(),
+    });
+    let app_id = window.and_then(|w| w.app_id.as_deref());
+
+    let text = match (workspace_text, app_id) {
+        (Some(ws), Some(app_id)) => format!("{ws} {app_id}"),
+        (Some(ws), None) => ws,
+        (None, app_id) => app_id.unwrap_or_default().to_string(),
+    };
+    let tooltip = window.and_then(|w| w.title.as_deref()).unwrap_or_default();
+    let class = match window {
+        Some(w) if w.is_floating => "floating",
+        Some(_) => "tiled",
+        None => "empty",
+    };
+
+    json!({ "text": text, "tooltip": tooltip, "class": class })
+}
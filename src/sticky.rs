@@ -0,0 +1,72 @@
+use anyhow::Result;
+use niri_ipc::Event;
+use std::ops::ControlFlow;
+
+use crate::{Corner, GlobalArgs, config::Config, ipc::Niri, snap_floating_on};
+
+/// Keep the floating window snapped to `corner` until it is closed, reconnecting whenever the
+/// connection drops.
+pub fn run(args: &GlobalArgs, corner: &Corner) -> Result<()> {
+    let config = Config::for_args(args)?;
+    let id = Niri::connect(args)?.floating_window()?.id;
+    let direction = corner.direction();
+
+    // The output geometry and tile size the window was last snapped for. niri doesn't send events
+    // for output changes, but they always come with changes to the workspaces or the config.
+    let mut snapped = None;
+
+    // The window is snapped whenever its output or its size changed, until it is closed.
+    Niri::for_each_event_reconnecting(args, |niri, event| {
+        let relevant = match event {
+            Event::WindowClosed { id: closed } => {
+                if closed == id {
+                    return Ok(ControlFlow::Break(()));
+                }
+                false
+            }
+            Event::WindowOpenedOrChanged { window } => window.id == id,
+            Event::WindowLayoutsChanged { changes } => changes.iter().any(|(w, _)| *w == id),
+            Event::WindowsChanged { .. }
+            | Event::WorkspacesChanged { .. }
+            | Event::WorkspaceActivated { .. }
+            | Event::ConfigLoaded { .. } => true,
+            _ => false,
+        };
+        if !relevant {
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        let Some(window) = niri.windows()?.into_iter().find(|w| w.id == id) else {
+            return Ok(ControlFlow::Break(()));
+        };
+        // The window may be floated again later, so keep waiting instead of giving up.
+        if !window.is_floating {
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        // While a monitor is unplugged its workspaces are briefly on no output.
+        let Ok(output) = niri.current_output(&window) else {
+            return Ok(ControlFlow::Continue(()));
+        };
+
+        let geometry = (
+            output.name.clone(),
+            output.logical.map(|l| (l.x, l.y, l.width, l.height)),
+            window.layout.tile_size,
+        );
+        if snapped.as_ref() == Some(&geometry) {
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        snap_floating_on(
+            niri,
+            &window,
+            std::slice::from_ref(&direction),
+            &output,
+            &config,
+        )?;
+        snapped = Some(geometry);
+
+        Ok(ControlFlow::Continue(()))
+    })
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use niri_ipc::Event;
+use std::{collections::HashSet, ops::ControlFlow};
+
+use crate::{GlobalArgs, config::Config, ipc::Niri, snap_floating};
+
+/// Snap newly floating windows matching a rule, reconnecting whenever the connection drops.
+///
+/// There's no state worth saving on exit, so SIGTERM is left to terminate the process.
+pub fn run(args: &GlobalArgs) -> Result<()> {
+    let config = Config::for_args(args)?;
+
+    // Floating windows that were already there or already checked against the rules, so that they
+    // are left alone when they change later on.
+    let mut floating = HashSet::new();
+
+    Niri::for_each_event_reconnecting(args, |niri, event| {
+        match event {
+            Event::WindowsChanged { windows } => {
+                floating = windows
+                    .into_iter()
+                    .filter(|w| w.is_floating)
+                    .map(|w| w.id)
+                    .collect();
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                if !window.is_floating {
+                    floating.remove(&window.id);
+                    return Ok(ControlFlow::Continue(()));
+                }
+                if floating.contains(&window.id) {
+                    return Ok(ControlFlow::Continue(()));
+                }
+
+                // The app id is often set after the window opened, so windows without one are
+                // checked again when they change.
+                let Some(app_id) = window.app_id.as_deref().filter(|id| !id.is_empty()) else {
+                    return Ok(ControlFlow::Continue(()));
+                };
+                floating.insert(window.id);
+
+                if let Some(rule) = config.rules.iter().find(|rule| rule.app_id == app_id) {
+                    snap_floating(niri, &window, std::slice::from_ref(&rule.snap), &config)?;
+                }
+            }
+            Event::WindowClosed { id } => {
+                floating.remove(&id);
+            }
+            _ => (),
+        }
+
+        Ok(ControlFlow::Continue(()))
+    })
+}
@@ -0,0 +1,190 @@
+//! Tests running myniri against a real niri.
+//!
+//! These are ignored by default, as they need niri and a Wayland client to open windows with.
+//! niri has to be able to start from the test, which works nested inside another Wayland session.
+//! Run them with `cargo test -- --ignored --test-threads 1`. `$MYNIRI_TEST_NIRI` and
+//! `$MYNIRI_TEST_CLIENT` choose the programs, `niri` and `foot` by default.
+
+use niri_ipc::Window;
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Child, Command, Output, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long to wait for niri and its windows.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A niri running with its own runtime directory and an empty config, killed on drop.
+struct Niri {
+    child: Child,
+    dir: PathBuf,
+    socket: PathBuf,
+}
+
+impl Niri {
+    fn start(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("myniri-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let config = dir.join("niri.kdl");
+        fs::write(&config, "hotkey-overlay {\n    skip-at-startup\n}\n").unwrap();
+        // myniri's config, empty so that the default margins are used.
+        fs::write(dir.join("myniri.toml"), "").unwrap();
+
+        let niri = std::env::var("MYNIRI_TEST_NIRI").unwrap_or_else(|_| "niri".to_string());
+        let mut command = Command::new(niri);
+        // niri looks for the Wayland session it runs nested in relative to its runtime directory,
+        // which is a new one here.
+        if let Some(display) = host_wayland_display() {
+            command.env("WAYLAND_DISPLAY", display);
+        }
+        let child = command
+            .arg("--config")
+            .arg(&config)
+            .env("XDG_RUNTIME_DIR", &dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start niri");
+
+        let socket = wait_for(|| {
+            fs::read_dir(&dir)
+                .ok()?
+                .flatten()
+                .map(|e| e.path())
+                .find(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with("niri.") && name.ends_with(".sock"))
+                })
+        });
+
+        Self { child, dir, socket }
+    }
+
+    /// Run myniri with `args` against this niri, with its state files in the directory of the test.
+    fn myniri(&self, args: &[&str]) -> Output {
+        let output = Command::new(env!("CARGO_BIN_EXE_myniri"))
+            .env("XDG_STATE_HOME", &self.dir)
+            .arg("--socket")
+            .arg(&self.socket)
+            .arg("--config")
+            .arg(self.dir.join("myniri.toml"))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "myniri {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    fn windows(&self) -> Vec<Window> {
+        let output = self.myniri(&["windows", "--json"]);
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+
+    /// Open a window of the test client and return it once niri shows it.
+    fn open_window(&self) -> Window {
+        let before = self.windows().len();
+
+        let client = std::env::var("MYNIRI_TEST_CLIENT").unwrap_or_else(|_| "foot".to_string());
+        let request = serde_json::json!({ "Action": { "Spawn": { "command": [client] } } });
+        self.myniri(&["raw", &request.to_string()]);
+
+        wait_for(|| {
+            let windows = self.windows();
+            (windows.len() > before)
+                .then(|| windows.into_iter().max_by_key(|w| w.id))
+                .flatten()
+        })
+    }
+
+    fn window(&self, id: u64) -> Window {
+        self.windows().into_iter().find(|w| w.id == id).unwrap()
+    }
+}
+
+impl Drop for Niri {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// The absolute path of the socket of the Wayland session the tests run in, if any.
+fn host_wayland_display() -> Option<PathBuf> {
+    let display = PathBuf::from(std::env::var_os("WAYLAND_DISPLAY")?);
+    if display.is_absolute() {
+        return Some(display);
+    }
+    Some(PathBuf::from(std::env::var_os("XDG_RUNTIME_DIR")?).join(display))
+}
+
+/// Call `f` until it returns something, failing the test after [`TIMEOUT`].
+fn wait_for<T>(mut f: impl FnMut() -> Option<T>) -> T {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let Some(value) = f() {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "timed out");
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+#[ignore = "needs niri and a Wayland client"]
+fn floating_snap_or_moves_to_the_corner() {
+    let niri = Niri::start("snap");
+    let window = niri.open_window();
+    let id = window.id.to_string();
+
+    niri.myniri(&["toggle-floating", "--window-id", &id]);
+    wait_for(|| niri.window(window.id).is_floating.then_some(()));
+
+    niri.myniri(&[
+        "--window-id",
+        &id,
+        "floating-snap-or",
+        "--direction",
+        "top-left",
+        "focus-column-left",
+    ]);
+
+    // The default margins are 0 at the top and at the left.
+    wait_for(|| {
+        let pos = niri.window(window.id).layout.tile_pos_in_workspace_view?;
+        (pos == (0., 0.)).then_some(())
+    });
+}
+
+#[test]
+#[ignore = "needs niri and a Wayland client"]
+fn consume_into_left_joins_the_columns() {
+    let niri = Niri::start("consume");
+    let first = niri.open_window();
+    let second = niri.open_window();
+
+    let column = |id| {
+        niri.window(id)
+            .layout
+            .pos_in_scrolling_layout
+            .map(|(c, _)| c)
+    };
+    assert_eq!(column(first.id), Some(1));
+    assert_eq!(column(second.id), Some(2));
+
+    niri.myniri(&["--window-id", &second.id.to_string(), "consume-into-left"]);
+
+    wait_for(|| (column(second.id) == Some(1)).then_some(()));
+    assert_eq!(column(first.id), Some(1));
+    assert!(niri.window(second.id).is_focused);
+}